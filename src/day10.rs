@@ -1,106 +1,245 @@
 use itertools::Itertools;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 
+struct TrailAnalysis {
+    reachable: HashMap<(i32, i32), HashSet<(i32, i32)>>,
+    paths: HashMap<(i32, i32), u64>,
+}
+
+impl TrailAnalysis {
+    fn score(&self, pos: &(i32, i32)) -> u64 {
+        self.reachable
+            .get(pos)
+            .map_or(0, |nines| nines.len() as u64)
+    }
+
+    fn rating(&self, pos: &(i32, i32)) -> u64 {
+        self.paths.get(pos).copied().unwrap_or(0)
+    }
+}
+
+struct StepRules {
+    min_delta: i32,
+    max_delta: i32,
+    diagonal: bool,
+    start_height: u32,
+    end_height: u32,
+}
+
+impl StepRules {
+    fn standard() -> Self {
+        Self {
+            min_delta: 1,
+            max_delta: 1,
+            diagonal: false,
+            start_height: 0,
+            end_height: 9,
+        }
+    }
+
+    fn allows(&self, from_height: u32, to_height: u32) -> bool {
+        (self.min_delta..=self.max_delta).contains(&(to_height as i32 - from_height as i32))
+    }
+
+    fn neighbours(&self, (row, col): (i32, i32)) -> Vec<(i32, i32)> {
+        let mut neighbours = vec![
+            (row + 1, col),
+            (row - 1, col),
+            (row, col + 1),
+            (row, col - 1),
+        ];
+        if self.diagonal {
+            neighbours.extend([
+                (row + 1, col + 1),
+                (row + 1, col - 1),
+                (row - 1, col + 1),
+                (row - 1, col - 1),
+            ]);
+        }
+        neighbours
+    }
+}
+
 struct TopoMap {
     cells: HashMap<(i32, i32), u32>,
+    rules: StepRules,
 }
 
 impl TopoMap {
     fn new(content: &str) -> Self {
+        TopoMap::with_rules(content, StepRules::standard())
+    }
+
+    fn with_rules(content: &str, rules: StepRules) -> Self {
         let cells = content
             .lines()
             .enumerate()
-            .map(|(row, line)| {
-                line.chars().enumerate().map(move |(col, ch)| {
-                    ((row as i32, col as i32), ch.to_digit(10).unwrap_or(100))
-                })
+            .flat_map(|(row, line)| {
+                line.chars()
+                    .enumerate()
+                    .filter_map(move |(col, ch)| Some(((row as i32, col as i32), ch.to_digit(10)?)))
             })
-            .flatten()
             .collect();
-        Self { cells }
+        Self { cells, rules }
     }
 
-    fn trailheads_score(&self) -> u64 {
+    fn trailheads_score(&self, analysis: &TrailAnalysis) -> u64 {
         self.trailheads()
             .iter()
-            .map(|pos| self.trailhead_score(pos))
+            .map(|pos| analysis.score(pos))
             .sum()
     }
 
-    fn trailhead_score(&self, pos: &(i32, i32)) -> u64 {
-        self.distinct_9_reachable_from(pos).iter().count() as u64
+    fn trailheads_rating(&self, analysis: &TrailAnalysis) -> u64 {
+        self.trailheads()
+            .iter()
+            .map(|pos| analysis.rating(pos))
+            .sum()
     }
 
-    fn distinct_9_reachable_from(&self, from: &(i32, i32)) -> Vec<(i32, i32)> {
-        if *self.cells.get(from).unwrap() == 9 {
-            vec![from.clone()]
-        } else {
-            self.moves_from(from)
-                .iter()
-                .map(|next_pos| self.distinct_9_reachable_from(next_pos))
-                .flatten()
-                .unique()
-                .collect()
-        }
+    fn is_end(&self, pos: &(i32, i32)) -> bool {
+        self.cells[pos] == self.rules.end_height
     }
 
-    fn trailheads_rating(&self) -> u64 {
-        self.trailheads()
+    fn topological_order(&self) -> Option<Vec<(i32, i32)>> {
+        let mut incoming: HashMap<(i32, i32), usize> =
+            self.cells.keys().map(|&pos| (pos, 0)).collect();
+        for pos in self.cells.keys().filter(|pos| !self.is_end(pos)) {
+            for next_pos in self.moves_from(pos) {
+                *incoming.get_mut(&next_pos).unwrap() += 1;
+            }
+        }
+        let mut ready: Vec<(i32, i32)> = incoming
             .iter()
-            .map(|pos| self.count_paths_to_9(pos))
-            .sum()
+            .filter(|(_pos, &count)| count == 0)
+            .map(|(&pos, _)| pos)
+            .collect();
+        let mut order = vec![];
+        while let Some(pos) = ready.pop() {
+            order.push(pos);
+            if self.is_end(&pos) {
+                continue;
+            }
+            for next_pos in self.moves_from(&pos) {
+                let count = incoming.get_mut(&next_pos).unwrap();
+                *count -= 1;
+                if *count == 0 {
+                    ready.push(next_pos);
+                }
+            }
+        }
+        match order.len() == self.cells.len() {
+            true => Some(order),
+            false => None,
+        }
     }
 
-    fn count_paths_to_9(&self, from: &(i32, i32)) -> u64 {
-        if *self.cells.get(from).unwrap() == 9 {
-            1
-        } else {
-            self.moves_from(from)
-                .iter()
-                .map(|next_pos| self.count_paths_to_9(next_pos))
-                .sum()
+    fn analyse(&self) -> Option<TrailAnalysis> {
+        let mut reachable: HashMap<(i32, i32), HashSet<(i32, i32)>> = HashMap::new();
+        let mut paths: HashMap<(i32, i32), u64> = HashMap::new();
+        for pos in self.topological_order()?.into_iter().rev() {
+            if self.is_end(&pos) {
+                reachable.insert(pos, HashSet::from([pos]));
+                paths.insert(pos, 1);
+            } else {
+                let next = self.moves_from(&pos);
+                let reachable_ends = next
+                    .iter()
+                    .flat_map(|next_pos| reachable[next_pos].iter().copied())
+                    .collect();
+                reachable.insert(pos, reachable_ends);
+                paths.insert(pos, next.iter().map(|next_pos| paths[next_pos]).sum());
+            }
         }
+        Some(TrailAnalysis { reachable, paths })
+    }
+
+    fn trails_from(&self, from: (i32, i32)) -> Trails<'_> {
+        Trails {
+            map: self,
+            path: vec![],
+            pending: vec![vec![from]],
+        }
+    }
+
+    fn sample_trails(&self, cap: usize) -> Vec<Vec<(i32, i32)>> {
+        self.trailheads()
+            .into_iter()
+            .sorted()
+            .flat_map(|pos| self.trails_from(pos))
+            .take(cap)
+            .collect()
     }
 
     fn trailheads(&self) -> Vec<(i32, i32)> {
         self.cells
             .iter()
-            .filter(|(_pos, &height)| height == 0)
+            .filter(|(_pos, &height)| height == self.rules.start_height)
             .map(|(pos, _)| *pos)
             .collect()
     }
 
     fn moves_from(&self, from: &(i32, i32)) -> Vec<(i32, i32)> {
         let current_height = *self.cells.get(from).unwrap();
-        [
-            (from.0 + 1, from.1),
-            (from.0 - 1, from.1),
-            (from.0, from.1 + 1),
-            (from.0, from.1 - 1),
-        ]
-        .iter()
-        .filter(|pos| {
-            self.cells
-                .get(*pos)
-                .filter(|&&adjacent_height| adjacent_height == current_height + 1)
-                .is_some()
-        })
-        .map(|pos| *pos)
-        .collect()
+        self.rules
+            .neighbours(*from)
+            .into_iter()
+            .filter(|pos| {
+                self.cells
+                    .get(pos)
+                    .filter(|&&adjacent_height| self.rules.allows(current_height, adjacent_height))
+                    .is_some()
+            })
+            .collect()
+    }
+}
+
+struct Trails<'a> {
+    map: &'a TopoMap,
+    path: Vec<(i32, i32)>,
+    pending: Vec<Vec<(i32, i32)>>,
+}
+
+impl Iterator for Trails<'_> {
+    type Item = Vec<(i32, i32)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let Some(pos) = self.pending.last_mut()?.pop() else {
+                self.pending.pop();
+                self.path.pop();
+                continue;
+            };
+            if self.path.contains(&pos) {
+                continue;
+            }
+            self.path.push(pos);
+            if self.map.is_end(&pos) {
+                let trail = self.path.clone();
+                self.path.pop();
+                return Some(trail);
+            }
+            self.pending.push(self.map.moves_from(&pos));
+        }
     }
 }
 
-fn part2(map: &TopoMap) -> u64 {
-    map.trailheads_rating()
+fn part2(map: &TopoMap, analysis: &TrailAnalysis) -> u64 {
+    map.trailheads_rating(analysis)
 }
-fn part1(map: &TopoMap) -> u64 {
-    map.trailheads_score()
+fn part1(map: &TopoMap, analysis: &TrailAnalysis) -> u64 {
+    map.trailheads_score(analysis)
 }
 
 pub(crate) fn solve() {
     let content = fs::read_to_string("10.txt").unwrap();
     let map = TopoMap::new(&content);
-    println!("{}", part1(&map));
-    println!("{}", part2(&map));
+    match map.analyse() {
+        Some(analysis) => {
+            println!("{}", part1(&map, &analysis));
+            println!("{}", part2(&map, &analysis));
+        }
+        None => println!("step rules allow cycles, trails are unbounded"),
+    }
 }
@@ -1,66 +1,198 @@
 use std::collections::{HashMap, VecDeque};
+use std::fmt::{Display, Formatter};
 use std::fs;
 
+type Predicate = Box<dyn Fn(u128) -> bool>;
+type Transformation = Box<dyn Fn(u128) -> Option<Vec<u128>>>;
+
+struct Rule {
+    applies: Predicate,
+    transform: Transformation,
+}
+
+impl Rule {
+    fn new(applies: Predicate, transform: Transformation) -> Self {
+        Self { applies, transform }
+    }
+
+    fn replace(value: u128, replacement: u128) -> Self {
+        Rule::new(
+            Box::new(move |stone| stone == value),
+            Box::new(move |_| Some(vec![replacement])),
+        )
+    }
+
+    fn split_digits(parts: usize) -> Self {
+        Rule::new(
+            Box::new(move |stone| (Rule::digits(stone) as usize).is_multiple_of(parts)),
+            Box::new(move |stone| {
+                let divisor = 10_u128.pow(Rule::digits(stone) / parts as u32);
+                let mut rest = stone;
+                let mut split: Vec<u128> = (0..parts)
+                    .map(|_| {
+                        let part = rest % divisor;
+                        rest /= divisor;
+                        part
+                    })
+                    .collect();
+                split.reverse();
+                Some(split)
+            }),
+        )
+    }
+
+    fn multiply(factor: u128) -> Self {
+        Rule::new(
+            Box::new(|_| true),
+            Box::new(move |stone| Some(vec![stone.checked_mul(factor)?])),
+        )
+    }
+
+    fn digits(stone: u128) -> u32 {
+        stone.checked_ilog10().map_or(1, |log| log + 1)
+    }
+}
+
+struct Rules {
+    rules: Vec<Rule>,
+}
+
+impl Rules {
+    fn new() -> Self {
+        Self { rules: vec![] }
+    }
+
+    fn standard() -> Self {
+        let mut rules = Rules::new();
+        rules.add(Rule::replace(0, 1));
+        rules.add(Rule::split_digits(2));
+        rules.add(Rule::multiply(2024));
+        rules
+    }
+
+    fn add(&mut self, rule: Rule) {
+        self.rules.push(rule);
+    }
+
+    fn apply(&self, stone: u128) -> Option<Vec<u128>> {
+        match self.rules.iter().find(|rule| (rule.applies)(stone)) {
+            Some(rule) => (rule.transform)(stone),
+            None => Some(vec![stone]),
+        }
+    }
+}
+
 struct Stones {
     stones: VecDeque<u128>,
+    rules: Rules,
 }
 
 impl Stones {
     fn new(content: &str) -> Self {
+        Stones::with_rules(content, Rules::standard())
+    }
+
+    fn with_rules(content: &str, rules: Rules) -> Self {
         Self {
             stones: content
                 .split_whitespace()
                 .map(|c| c.parse().unwrap())
                 .collect(),
+            rules,
         }
     }
-    fn blink(&self, steps: usize) -> usize {
-        let mut known = HashMap::new();
-        self.stones
-            .iter()
-            .map(|s| self.expand_stone(*s, steps, &mut known))
-            .sum()
-    }
-
-    fn expand_stone(
-        &self,
-        stone: u128,
-        steps_left: usize,
-        known: &mut HashMap<(u128, usize), usize>,
-    ) -> usize {
-        if steps_left == 0 {
-            return 1;
+
+    fn blink(&self, steps: usize) -> Result<usize, Overflow> {
+        self.evolve(steps)?.total().ok_or(Overflow::Total { steps })
+    }
+
+    fn evolve(&self, steps: usize) -> Result<Distribution, Overflow> {
+        let mut counts: HashMap<u128, usize> = HashMap::new();
+        for &stone in &self.stones {
+            *counts.entry(stone).or_default() += 1;
+        }
+        for step in 0..steps {
+            let mut next_counts: HashMap<u128, usize> = HashMap::new();
+            for (stone, count) in counts {
+                let next_stones = self
+                    .rules
+                    .apply(stone)
+                    .ok_or(Overflow::Value { step, stone })?;
+                for next in next_stones {
+                    let next_count = next_counts.entry(next).or_default();
+                    *next_count = next_count
+                        .checked_add(count)
+                        .ok_or(Overflow::Count { step, stone: next })?;
+                }
+            }
+            counts = next_counts;
         }
-        if known.contains_key(&(stone, steps_left)) {
-            *known.get(&(stone, steps_left)).unwrap()
-        } else {
-            let digits = stone.to_string();
-            let res = if stone.eq(&0) {
-                self.expand_stone(1, steps_left - 1, known)
-            } else if digits.len() % 2 == 0 {
-                let left = digits[0..digits.len() / 2].parse::<u128>().unwrap();
-                let right = digits[digits.len() / 2..].parse::<u128>().unwrap();
-                self.expand_stone(left, steps_left - 1, known)
-                    + self.expand_stone(right, steps_left - 1, known)
-            } else {
-                self.expand_stone(stone * 2024, steps_left - 1, known)
-            };
-            known.insert((stone, steps_left), res);
-            res
+        Ok(Distribution { counts })
+    }
+}
+
+#[derive(Debug)]
+enum Overflow {
+    Value { step: usize, stone: u128 },
+    Count { step: usize, stone: u128 },
+    Total { steps: usize },
+}
+
+impl Display for Overflow {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Overflow::Value { step, stone } => {
+                write!(
+                    f,
+                    "blink {}: stone {} overflows when transformed",
+                    step + 1,
+                    stone
+                )
+            }
+            Overflow::Count { step, stone } => {
+                write!(f, "blink {}: count of stone {} overflows", step + 1, stone)
+            }
+            Overflow::Total { steps } => {
+                write!(f, "total stone count after {} blinks overflows", steps)
+            }
         }
     }
 }
 
-fn part2(stones: &Stones) -> usize {
+struct Distribution {
+    counts: HashMap<u128, usize>,
+}
+
+impl Distribution {
+    fn total(&self) -> Option<usize> {
+        self.counts
+            .values()
+            .try_fold(0_usize, |total, &count| total.checked_add(count))
+    }
+
+    fn distinct(&self) -> usize {
+        self.counts.len()
+    }
+
+    fn largest(&self) -> Option<u128> {
+        self.counts.keys().max().copied()
+    }
+}
+
+fn part2(stones: &Stones) -> Result<usize, Overflow> {
     stones.blink(75)
 }
 
-fn part1(stones: &Stones) -> usize {
+fn part1(stones: &Stones) -> Result<usize, Overflow> {
     stones.blink(25)
 }
 pub(crate) fn solve() {
     let content = fs::read_to_string("11.txt").unwrap();
     let stones = Stones::new(&content);
-    println!("{}", part1(&stones));
-    println!("{}", part2(&stones));
+    for result in [part1(&stones), part2(&stones)] {
+        match result {
+            Ok(count) => println!("{}", count),
+            Err(overflow) => println!("{}", overflow),
+        }
+    }
 }
@@ -1,4 +1,3 @@
-use crate::day12::EdgeDirection::{Horizontal, Vertical};
 use itertools::Itertools;
 use std::collections::{HashMap, HashSet, VecDeque};
 use std::fs;
@@ -32,13 +31,46 @@ impl Board {
                 let label = self.cells[pos];
                 let points = bfs(&point, |p| self.neighbours(p));
                 visited.extend(points.iter().map(|p| (p.x, p.y)));
-                let region = Region { label, points };
+                let region = Region::new(label, points);
                 regions.push(region);
             }
         }
         regions
     }
 
+    fn render_fences(&self) -> String {
+        let width = self.cells.keys().map(|&(x, _)| x).max().unwrap_or(-1) + 1;
+        let height = self.cells.keys().map(|&(_, y)| y).max().unwrap_or(-1) + 1;
+        let fenced = |a: (i64, i64), b: (i64, i64)| self.cells.get(&a) != self.cells.get(&b);
+        (0..=2 * height)
+            .map(|row| {
+                (0..=2 * width)
+                    .map(|col| {
+                        let (x, y) = (col / 2, row / 2);
+                        match (col % 2, row % 2) {
+                            (1, 1) => self.cells.get(&(x, y)).copied().unwrap_or(' '),
+                            (0, 1) if fenced((x - 1, y), (x, y)) => '|',
+                            (1, 0) if fenced((x, y - 1), (x, y)) => '-',
+                            (0, 0) => {
+                                let around = [(x - 1, y - 1), (x, y - 1), (x, y), (x - 1, y)];
+                                let touching = around
+                                    .iter()
+                                    .circular_tuple_windows()
+                                    .any(|(&a, &b)| fenced(a, b));
+                                if touching {
+                                    '+'
+                                } else {
+                                    ' '
+                                }
+                            }
+                            _ => ' ',
+                        }
+                    })
+                    .collect::<String>()
+            })
+            .join("\n")
+    }
+
     fn neighbours(&self, point: &Point) -> Vec<Point> {
         let x = point.x;
         let y = point.y;
@@ -57,171 +89,108 @@ struct Point {
     y: i64,
 }
 
-#[derive(PartialEq, Eq, Hash, Clone, Copy)]
-enum EdgeDirection {
-    Horizontal,
-    Vertical,
-}
-
-impl EdgeDirection {
-    fn deltas(&self) -> Vec<(i64, i64)> {
-        match self {
-            Horizontal => vec![(-1, 0), (1, 0)],
-            Vertical => vec![(0, -1), (0, 1)],
-        }
-    }
-}
-
-#[derive(PartialEq, Eq, Hash, Clone, Copy)]
-enum EdgeLocation {
-    Left,
-    Right,
-    Up,
-    Down,
-}
-
-impl EdgeLocation {
-    fn deltas(&self) -> ((i64, i64), (i64, i64)) {
-        match self {
-            EdgeLocation::Left => ((0, 0), (0, 1)),
-            EdgeLocation::Right => ((1, 0), (1, 1)),
-            EdgeLocation::Up => ((0, 1), (1, 1)),
-            EdgeLocation::Down => ((0, 0), (1, 0)),
-        }
-    }
-
-    fn edge_for_point(&self, point: &Point) -> Edge {
-        let (x, y) = (point.x, point.y);
-        let ((first_dx, first_dy), (second_dx, second_dy)) = self.deltas();
-        Edge {
-            first: Point {
-                x: x + first_dx,
-                y: y + first_dy,
-            },
-            second: Point {
-                x: x + second_dx,
-                y: y + second_dy,
-            },
-            location: self.clone(),
-        }
-    }
-}
-
-#[derive(PartialEq, Eq, Hash, Clone, Copy)]
-struct Edge {
-    first: Point,
-    second: Point,
-    location: EdgeLocation,
+struct Boundary {
+    hole: bool,
+    perimeter: i64,
+    sides: i64,
 }
 
-impl Edge {
-    fn direction(&self) -> EdgeDirection {
-        if self.first.x == self.second.x {
-            Vertical
-        } else {
-            Horizontal
-        }
-    }
-}
+const SIDES: [(i64, i64); 4] = [(0, -1), (1, 0), (0, 1), (-1, 0)];
 
-impl Point {
-    fn surround_edges(&self) -> Vec<Edge> {
-        [
-            EdgeLocation::Left,
-            EdgeLocation::Right,
-            EdgeLocation::Up,
-            EdgeLocation::Down,
-        ]
-        .map(|location| location.edge_for_point(self))
-        .iter()
-        .map(|e| *e)
-        .collect()
-    }
-}
 struct Region {
     label: char,
     points: Vec<Point>,
+    boundaries: Vec<Boundary>,
 }
 
 impl Region {
+    fn new(label: char, points: Vec<Point>) -> Region {
+        let mut region = Region {
+            label,
+            points,
+            boundaries: vec![],
+        };
+        region.boundaries = region.trace_boundaries();
+        region
+    }
+
     fn area(&self) -> i64 {
         self.points.len() as i64
     }
 
-    fn perimeter_edges(&self) -> HashSet<Edge> {
-        let single_edges: HashSet<(Point, Point)> = self
-            .points
-            .iter()
-            .map(|point| point.surround_edges())
-            .flatten()
-            .map(|edge| (edge.first, edge.second))
-            .counts()
-            .iter()
-            .filter(|(_points, count)| **count == 1)
-            .map(|(points, _count)| *points)
-            .collect();
-        self.points
-            .iter()
-            .map(|point| point.surround_edges())
-            .flatten()
-            .filter(|e| single_edges.contains(&(e.first, e.second)))
-            .collect()
+    fn bounding_box(&self) -> (Point, Point) {
+        let min = Point {
+            x: self.points.iter().map(|p| p.x).min().unwrap(),
+            y: self.points.iter().map(|p| p.y).min().unwrap(),
+        };
+        let max = Point {
+            x: self.points.iter().map(|p| p.x).max().unwrap(),
+            y: self.points.iter().map(|p| p.y).max().unwrap(),
+        };
+        (min, max)
+    }
+
+    fn cells(&self) -> HashSet<(i64, i64)> {
+        self.points.iter().map(|p| (p.x, p.y)).collect()
     }
 
     fn perimeter(&self) -> i64 {
-        self.perimeter_edges().len() as i64
+        self.boundaries.iter().map(|b| b.perimeter).sum()
     }
 
     fn sides(&self) -> i64 {
-        let edges = self.perimeter_edges();
-        let mut sides = 0;
-        let mut visited_points = HashSet::new();
-        for e in &edges {
-            let edge_direction = e.direction();
-            for p in [e.first, e.second] {
-                if !visited_points.contains(&(p, edge_direction)) {
-                    let side = bfs(&p, |p| {
-                        self.neighbours(p, &edge_direction, &e.location, &edges)
-                    });
-                    sides += 1;
-                    for side_point in side {
-                        visited_points.insert((side_point, edge_direction));
-                    }
-                }
-            }
-        }
-        sides
+        self.boundaries.iter().map(|b| b.sides).sum()
+    }
+
+    fn holes(&self) -> usize {
+        self.boundaries.iter().filter(|b| b.hole).count()
     }
 
-    fn neighbours(
-        &self,
-        current: &Point,
-        edge_direction: &EdgeDirection,
-        edge_location: &EdgeLocation,
-        perimeter_edges: &HashSet<Edge>,
-    ) -> Vec<Point> {
-        let deltas = edge_direction.deltas();
-        let mut neighbours = vec![];
-        for (dx, dy) in deltas.iter() {
-            let neighbour = Point {
-                x: current.x + dx,
-                y: current.y + dy,
-            };
-            let e1 = Edge {
-                first: *current,
-                second: neighbour,
-                location: *edge_location,
-            };
-            let e2 = Edge {
-                first: neighbour,
-                second: *current,
-                location: *edge_location,
-            };
-            if perimeter_edges.contains(&e1) || perimeter_edges.contains(&e2) {
-                neighbours.push(neighbour);
+    // Walks each closed fence with the region on its right. Where two empty cells only meet at a
+    // corner, the walk stays with the empty cell it is following, so they end up in separate holes.
+    fn trace_boundaries(&self) -> Vec<Boundary> {
+        let cells = self.cells();
+        let top = self.points.iter().min_by_key(|p| (p.y, p.x)).unwrap();
+        let mut traced = HashSet::new();
+        let mut boundaries = vec![];
+        for point in &self.points {
+            for (side, &(dx, dy)) in SIDES.iter().enumerate() {
+                let start = ((point.x, point.y), side);
+                if cells.contains(&(point.x + dx, point.y + dy)) || traced.contains(&start) {
+                    continue;
+                }
+                let mut boundary = Boundary {
+                    hole: true,
+                    perimeter: 0,
+                    sides: 0,
+                };
+                let mut edge = start;
+                while traced.insert(edge) {
+                    let ((x, y), side) = edge;
+                    if edge == ((top.x, top.y), 0) {
+                        boundary.hole = false;
+                    }
+                    let along = (side + 1) % 4;
+                    let (dx, dy) = SIDES[side];
+                    let (ax, ay) = SIDES[along];
+                    let ahead = (x + ax, y + ay);
+                    let diagonal = (x + ax + dx, y + ay + dy);
+                    edge = if cells.contains(&diagonal) {
+                        boundary.sides += 1;
+                        (diagonal, (side + 3) % 4)
+                    } else if cells.contains(&ahead) {
+                        (ahead, side)
+                    } else {
+                        boundary.sides += 1;
+                        ((x, y), along)
+                    };
+                    boundary.perimeter += 1;
+                }
+                boundaries.push(boundary);
             }
         }
-        neighbours
+        boundaries.sort_by_key(|b| b.hole);
+        boundaries
     }
 
     fn fence_price(&self) -> i64 {
@@ -231,6 +200,117 @@ impl Region {
     fn bulk_fence_price(&self) -> i64 {
         self.area() * self.sides()
     }
+
+    fn report(&self) -> RegionReport {
+        let perimeter = self.perimeter();
+        let sides = self.sides();
+        RegionReport {
+            label: self.label,
+            area: self.area(),
+            perimeter,
+            sides,
+            bounding_box: self.bounding_box(),
+            fence_price: self.area() * perimeter,
+            bulk_fence_price: self.area() * sides,
+        }
+    }
+}
+
+struct RegionReport {
+    label: char,
+    area: i64,
+    perimeter: i64,
+    sides: i64,
+    bounding_box: (Point, Point),
+    fence_price: i64,
+    bulk_fence_price: i64,
+}
+
+struct GardenReport {
+    regions: Vec<RegionReport>,
+}
+
+impl GardenReport {
+    fn new(regions: &[Region]) -> Self {
+        Self {
+            regions: regions
+                .iter()
+                .map(|region| region.report())
+                .sorted_by_key(|report| {
+                    let (min, max) = report.bounding_box;
+                    (min.y, min.x, max.y, max.x, report.label)
+                })
+                .collect(),
+        }
+    }
+
+    fn to_csv(&self) -> String {
+        let header =
+            "label,area,perimeter,sides,min_x,min_y,max_x,max_y,fence_price,bulk_fence_price";
+        let rows = self.regions.iter().map(|r| {
+            let (min, max) = r.bounding_box;
+            format!(
+                "{},{},{},{},{},{},{},{},{},{}",
+                csv_field(&r.label.to_string()),
+                r.area,
+                r.perimeter,
+                r.sides,
+                min.x,
+                min.y,
+                max.x,
+                max.y,
+                r.fence_price,
+                r.bulk_fence_price
+            )
+        });
+        std::iter::once(header.to_string()).chain(rows).join("\n")
+    }
+
+    fn to_json(&self) -> String {
+        let regions = self
+            .regions
+            .iter()
+            .map(|r| {
+                let (min, max) = r.bounding_box;
+                format!(
+                    "{{\"label\":{},\"area\":{},\"perimeter\":{},\"sides\":{},\"bounding_box\":[[{},{}],[{},{}]],\"fence_price\":{},\"bulk_fence_price\":{}}}",
+                    json_string(&r.label.to_string()),
+                    r.area,
+                    r.perimeter,
+                    r.sides,
+                    min.x,
+                    min.y,
+                    max.x,
+                    max.y,
+                    r.fence_price,
+                    r.bulk_fence_price
+                )
+            })
+            .join(",");
+        format!("[{}]", regions)
+    }
+}
+
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+fn json_string(text: &str) -> String {
+    let mut escaped = String::from('"');
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
 }
 
 fn part2(regions: &Vec<Region>) -> i64 {
@@ -1,78 +1,471 @@
+use itertools::Itertools;
 use regex::Regex;
+use std::cmp::Reverse;
+use std::fmt::{Display, Formatter};
 use std::fs;
 
+const BIG_OFFSET: (i128, i128) = (10000000000000, 10000000000000);
+const SEARCH_LIMIT: i128 = 10_000_000;
+
+#[derive(Clone)]
+struct Button {
+    movement: (i128, i128),
+    cost: i128,
+    limit: Option<i128>,
+}
+
+impl Button {
+    fn new(movement: (i128, i128), cost: i128) -> Self {
+        Self {
+            movement,
+            cost,
+            limit: None,
+        }
+    }
+
+    fn unused() -> Self {
+        Self {
+            movement: (0, 0),
+            cost: 0,
+            limit: Some(0),
+        }
+    }
+
+    fn allows(&self, presses: i128) -> bool {
+        presses >= 0 && self.limit.is_none_or(|limit| presses <= limit)
+    }
+
+    fn bound(&self, prize: (i128, i128), monotone: bool) -> Option<i128> {
+        let derived = if self.movement == (0, 0) {
+            Some(0)
+        } else if monotone {
+            [(self.movement.0, prize.0), (self.movement.1, prize.1)]
+                .iter()
+                .filter(|&&(step, _)| step > 0)
+                .map(|&(step, target)| target / step)
+                .min()
+        } else {
+            None
+        };
+        match (derived, self.limit) {
+            (Some(derived), Some(limit)) => Some(derived.min(limit)),
+            (derived, limit) => derived.or(limit),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum MachineError {
+    MissingPrize,
+    InvalidNumber { text: String },
+    CostCount { buttons: usize, costs: usize },
+}
+
+impl Display for MachineError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MachineError::MissingPrize => write!(f, "machine has no prize line"),
+            MachineError::InvalidNumber { text } => write!(f, "invalid number `{}`", text),
+            MachineError::CostCount { buttons, costs } => {
+                write!(f, "{} buttons but {} costs", buttons, costs)
+            }
+        }
+    }
+}
+
 struct Machine {
-    a: (i128, i128),
-    b: (i128, i128),
+    buttons: Vec<Button>,
     prize: (i128, i128),
 }
 
 impl Machine {
-    fn new(block: &str) -> Self {
-        let re = Regex::new(r"X.?(\d+).*Y.?(\d+)").unwrap();
+    fn new(block: &str) -> Result<Self, MachineError> {
+        Self::with_costs(block, &[3, 1])
+    }
+
+    fn with_costs(block: &str, costs: &[i128]) -> Result<Self, MachineError> {
+        let re = Regex::new(r"X[+=]?(-?\d+).*Y[+=]?(-?\d+)").unwrap();
+        let number = |text: &str| {
+            text.parse::<i128>()
+                .map_err(|_| MachineError::InvalidNumber {
+                    text: text.to_string(),
+                })
+        };
         let mut res = vec![];
         for capture in re.captures_iter(block) {
-            let x = capture.get(1).unwrap().as_str().parse::<i128>().unwrap();
-            let y = capture.get(2).unwrap().as_str().parse::<i128>().unwrap();
+            let x = number(capture.get(1).unwrap().as_str())?;
+            let y = number(capture.get(2).unwrap().as_str())?;
             res.push((x, y));
         }
-        Self {
-            a: res[0],
-            b: res[1],
-            prize: res[2],
+        let prize = res.pop().ok_or(MachineError::MissingPrize)?;
+        if res.len() != costs.len() {
+            return Err(MachineError::CostCount {
+                buttons: res.len(),
+                costs: costs.len(),
+            });
         }
+        Ok(Self {
+            buttons: res
+                .into_iter()
+                .zip(costs)
+                .map(|(movement, &cost)| Button::new(movement, cost))
+                .collect(),
+            prize,
+        })
     }
 
-    fn small_prize(&self) -> (i128, i128) {
-        self.prize
+    fn limit_presses(&mut self, limit: i128) {
+        for button in self.buttons.iter_mut() {
+            button.limit = Some(limit);
+        }
     }
 
-    fn big_prize(&self) -> (i128, i128) {
-        (self.prize.0 + 10000000000000, self.prize.1 + 10000000000000)
+    fn solve_small(&self) -> Outcome {
+        self.solve((0, 0))
+    }
+    fn solve_big(&self) -> Outcome {
+        self.solve(BIG_OFFSET)
     }
 
-    fn solve_small(&self) -> i128 {
-        self.solve(self.small_prize())
+    fn is_degenerate(&self) -> bool {
+        self.buttons.iter().all(|button| button.movement == (0, 0))
+    }
+
+    fn is_monotone(&self, prize: (i128, i128)) -> bool {
+        prize.0 >= 0
+            && prize.1 >= 0
+            && self
+                .buttons
+                .iter()
+                .all(|button| button.movement.0 >= 0 && button.movement.1 >= 0)
+    }
+
+    // the button with the lowest cost per unit of movement, when every button moves along one line
+    fn cheapest_on_line(&self, monotone: bool) -> Option<(usize, i128)> {
+        let direction = self.buttons.iter().find(|b| b.movement != (0, 0))?.movement;
+        let collinear = self
+            .buttons
+            .iter()
+            .all(|b| cross(b.movement, direction) == 0 && b.limit.is_none() && b.cost >= 0);
+        if !monotone || !collinear {
+            return None;
+        }
+        let weight = |b: &Button| b.movement.0.max(b.movement.1);
+        self.buttons
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.movement != (0, 0))
+            .min_by(|(_, x), (_, y)| (x.cost * weight(y)).cmp(&(y.cost * weight(x))))
+            .map(|(i, b)| (i, weight(b)))
+    }
+
+    fn solve(&self, offset: (i128, i128)) -> Outcome {
+        let prize = (self.prize.0 + offset.0, self.prize.1 + offset.1);
+        if prize == (0, 0) {
+            return Outcome::Solved {
+                presses: vec![0; self.buttons.len()],
+                cost: 0,
+            };
+        }
+        if self.is_degenerate() {
+            return Outcome::Degenerate;
+        }
+        let monotone = self.is_monotone(prize);
+        let mut bounds = self
+            .buttons
+            .iter()
+            .map(|button| button.bound(prize, monotone))
+            .collect_vec();
+        if let Some((cheapest, weight)) = self.cheapest_on_line(monotone) {
+            // any `weight` presses of the other buttons contain a subset whose length is a
+            // multiple of `weight`, which the cheapest button covers for no more
+            for (i, bound) in bounds.iter_mut().enumerate() {
+                if i != cheapest {
+                    *bound = bound.map(|bound| bound.min(weight - 1));
+                }
+            }
+        }
+        // a non-collinear pair of buttons with the widest range is solved exactly, the next one
+        // by residue class and the rest are enumerated
+        let mut order = (0..self.buttons.len())
+            .sorted_by_key(|&i| Reverse(bounds[i].unwrap_or(i128::MAX)))
+            .collect_vec();
+        if let Some((p, q)) = (0..order.len()).tuple_combinations().find(|&(p, q)| {
+            cross(
+                self.buttons[order[p]].movement,
+                self.buttons[order[q]].movement,
+            ) != 0
+        }) {
+            let second = order.remove(q);
+            let first = order.remove(p);
+            order.splice(0..0, [first, second]);
+        }
+        let (free, enumerated) = order.split_at(order.len().min(2));
+        if enumerated.iter().any(|&i| bounds[i].is_none()) {
+            return Outcome::Unbounded;
+        }
+        let unused = Button::unused();
+        let first = free.first().map_or(&unused, |&i| &self.buttons[i]);
+        let second = free.get(1).map_or(&unused, |&i| &self.buttons[i]);
+        let (stepped, enumerated) = match enumerated.split_first() {
+            Some((&stepped, rest)) => (Some(stepped), rest),
+            None => (None, enumerated),
+        };
+        let (third, third_bound) =
+            stepped.map_or((&unused, 0), |i| (&self.buttons[i], bounds[i].unwrap()));
+        let stepped_work = match cross(first.movement, second.movement) {
+            0 => third_bound + 1,
+            det => det.abs().min(third_bound + 1),
+        };
+        let work = enumerated.iter().try_fold(stepped_work, |work, &i| {
+            work.checked_mul(bounds[i].unwrap() + 1)
+        });
+        if work.is_none_or(|work| work > SEARCH_LIMIT) {
+            return Outcome::TooLarge;
+        }
+        let mut best: Option<(i128, Vec<i128>)> = None;
+        for counts in enumerated
+            .iter()
+            .map(|&i| 0..=bounds[i].unwrap())
+            .multi_cartesian_product()
+        {
+            let mut residual = prize;
+            let mut cost = 0;
+            for (&i, &count) in enumerated.iter().zip(counts.iter()) {
+                let button = &self.buttons[i];
+                residual.0 -= button.movement.0 * count;
+                residual.1 -= button.movement.1 * count;
+                cost += button.cost * count;
+            }
+            if best
+                .as_ref()
+                .is_some_and(|(best_cost, _)| cost >= *best_cost)
+            {
+                continue;
+            }
+            if let Some((n, k, m)) = solve_triple(third, third_bound, first, second, residual) {
+                let cost = cost + third.cost * n + first.cost * k + second.cost * m;
+                if best.as_ref().is_none_or(|(best_cost, _)| cost < *best_cost) {
+                    let mut presses = vec![0; self.buttons.len()];
+                    for (&i, &count) in enumerated.iter().zip(counts.iter()) {
+                        presses[i] = count;
+                    }
+                    for (&i, count) in free.iter().chain(stepped.iter()).zip([k, m, n]) {
+                        presses[i] = count;
+                    }
+                    best = Some((cost, presses));
+                }
+            }
+        }
+        match best {
+            Some((cost, presses)) => Outcome::Solved { presses, cost },
+            None => Outcome::Infeasible,
+        }
     }
-    fn solve_big(&self) -> i128 {
-        self.solve(self.big_prize())
+}
+
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Solved { presses: Vec<i128>, cost: i128 },
+    Infeasible,
+    Degenerate,
+    Unbounded,
+    TooLarge,
+}
+
+impl Outcome {
+    fn cost(&self) -> i128 {
+        match self {
+            Outcome::Solved { cost, .. } => *cost,
+            Outcome::Infeasible | Outcome::Degenerate | Outcome::Unbounded | Outcome::TooLarge => 0,
+        }
     }
+}
+
+fn solve_pair(a: &Button, b: &Button, prize: (i128, i128)) -> Option<(i128, i128)> {
+    let presses = if cross(a.movement, b.movement) == 0 {
+        solve_collinear(a, b, prize)
+    } else {
+        solve_unique(a, b, prize)
+    };
+    presses.filter(|&(k, m)| {
+        a.allows(k)
+            && b.allows(m)
+            && prize.0 == a.movement.0 * k + b.movement.0 * m
+            && prize.1 == a.movement.1 * k + b.movement.1 * m
+    })
+}
+
+fn solve_unique(a: &Button, b: &Button, prize: (i128, i128)) -> Option<(i128, i128)> {
+    let (ax, ay) = a.movement;
+    let (bx, by) = b.movement;
+    let (px, py) = prize;
+    let k = (bx * py - by * px) / (ay * bx - ax * by);
+    let m = (ay * px - ax * py) / (ay * bx - ax * by);
+    Some((k, m))
+}
 
-    fn solve(&self, prize: (i128, i128)) -> i128 {
-        let ax = self.a.0;
-        let ay = self.a.1;
-        let bx = self.b.0;
-        let by = self.b.1;
-        let (px, py) = prize;
-        let k = (bx * py - by * px) / (ay * bx - ax * by);
-        let m = (ay * px - ax * py) / (ay * bx - ax * by);
-        if self.check(k, m, prize) {
-            self.cost(k, m)
+fn solve_collinear(a: &Button, b: &Button, prize: (i128, i128)) -> Option<(i128, i128)> {
+    if cross(a.movement, prize) != 0 || cross(b.movement, prize) != 0 {
+        return None;
+    }
+    let project = |v: (i128, i128)| {
+        if a.movement.0 != 0 || b.movement.0 != 0 {
+            v.0
         } else {
-            0
+            v.1
         }
+    };
+    let (da, db, p) = (project(a.movement), project(b.movement), project(prize));
+    if da == 0 && db == 0 {
+        return (p == 0).then_some((0, 0));
+    }
+    if da == 0 {
+        return (p % db == 0).then_some((0, p / db));
+    }
+    if db == 0 {
+        return (p % da == 0).then_some((p / da, 0));
+    }
+    let (g, x, y) = extended_gcd(da, db);
+    if p % g != 0 {
+        return None;
+    }
+    // k = k0 + step_k * t, m = m0 - step_m * t
+    let (k0, m0) = (x * (p / g), y * (p / g));
+    let (step_k, step_m) = (db / g, da / g);
+    let mut constraints = vec![(k0, step_k), (m0, -step_m)];
+    if let Some(limit) = a.limit {
+        constraints.push((limit - k0, -step_k));
     }
+    if let Some(limit) = b.limit {
+        constraints.push((limit - m0, step_m));
+    }
+    let t = cheapest_step(&constraints, a.cost * step_k - b.cost * step_m)?;
+    Some((k0 + step_k * t, m0 - step_m * t))
+}
 
-    fn check(&self, k: i128, m: i128, prize: (i128, i128)) -> bool {
-        (prize.0 == self.a.0 * k + self.b.0 * m) && (prize.1 == self.a.1 * k + self.b.1 * m)
+fn solve_triple(
+    c: &Button,
+    bound: i128,
+    a: &Button,
+    b: &Button,
+    prize: (i128, i128),
+) -> Option<(i128, i128, i128)> {
+    let total = |&(n, k, m): &(i128, i128, i128)| c.cost * n + a.cost * k + b.cost * m;
+    let (ax, ay) = a.movement;
+    let (bx, by) = b.movement;
+    let (cx, cy) = c.movement;
+    let det = ay * bx - ax * by;
+    if det == 0 {
+        return (0..=bound)
+            .filter_map(|n| {
+                let residual = (prize.0 - cx * n, prize.1 - cy * n);
+                solve_pair(a, b, residual).map(|(k, m)| (n, k, m))
+            })
+            .min_by_key(total);
     }
+    // pressing c another |det| times shifts the pair solution by a fixed integer step,
+    // so each residue class of c is a one-dimensional problem
+    let period = det.abs();
+    let step_k = -(bx * cy - by * cx) * period / det;
+    let step_m = -(ay * cx - ax * cy) * period / det;
+    (0..period.min(bound + 1))
+        .filter_map(|r| {
+            let (px, py) = (prize.0 - cx * r, prize.1 - cy * r);
+            let (k_num, m_num) = (bx * py - by * px, ay * px - ax * py);
+            if k_num % det != 0 || m_num % det != 0 {
+                return None;
+            }
+            let (k0, m0) = (k_num / det, m_num / det);
+            let mut constraints = vec![
+                (0, 1),
+                ((bound - r) / period, -1),
+                (k0, step_k),
+                (m0, step_m),
+            ];
+            if let Some(limit) = a.limit {
+                constraints.push((limit - k0, -step_k));
+            }
+            if let Some(limit) = b.limit {
+                constraints.push((limit - m0, -step_m));
+            }
+            let slope = c.cost * period + a.cost * step_k + b.cost * step_m;
+            let t = cheapest_step(&constraints, slope)?;
+            Some((r + period * t, k0 + step_k * t, m0 + step_m * t))
+        })
+        .min_by_key(total)
+}
 
-    fn cost(&self, k: i128, m: i128) -> i128 {
-        k * 3 + m
+// cheapest integer t with base + step * t >= 0 for every constraint, for a cost growing by slope per step
+fn cheapest_step(constraints: &[(i128, i128)], slope: i128) -> Option<i128> {
+    let mut low = i128::MIN;
+    let mut high = i128::MAX;
+    for &(base, step) in constraints {
+        if step > 0 {
+            low = low.max(ceil_div(-base, step));
+        } else if step < 0 {
+            high = high.min(floor_div(-base, step));
+        } else if base < 0 {
+            return None;
+        }
+    }
+    if low > high {
+        return None;
+    }
+    match slope {
+        s if s > 0 && low != i128::MIN => Some(low),
+        s if s < 0 && high != i128::MAX => Some(high),
+        0 if low != i128::MIN => Some(low),
+        0 if high != i128::MAX => Some(high),
+        _ => None,
     }
 }
+
+fn cross(a: (i128, i128), b: (i128, i128)) -> i128 {
+    a.0 * b.1 - a.1 * b.0
+}
+
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, x, y) = extended_gcd(b, a % b);
+        (g, y, x - (a / b) * y)
+    }
+}
+
+fn floor_div(n: i128, d: i128) -> i128 {
+    let q = n / d;
+    if n % d != 0 && (n < 0) != (d < 0) {
+        q - 1
+    } else {
+        q
+    }
+}
+
+fn ceil_div(n: i128, d: i128) -> i128 {
+    -floor_div(-n, d)
+}
+
 fn part2(machines: &Vec<Machine>) -> i128 {
-    machines.iter().map(|m| m.solve_big()).sum()
+    machines.iter().map(|m| m.solve_big().cost()).sum()
 }
 fn part1(machines: &Vec<Machine>) -> i128 {
-    machines.iter().map(|m| m.solve_small()).sum()
+    machines.iter().map(|m| m.solve_small().cost()).sum()
 }
 pub(crate) fn solve() {
     let content = fs::read_to_string("13.txt").unwrap();
-    let machines = content
+    let machines = match content
         .split("\n\n")
         .map(|block| Machine::new(block))
-        .collect();
+        .collect::<Result<Vec<_>, _>>()
+    {
+        Ok(machines) => machines,
+        Err(error) => {
+            println!("{}", error);
+            return;
+        }
+    };
     println!("{}", part1(&machines));
     println!("{}", part2(&machines));
 }
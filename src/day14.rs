@@ -1,33 +1,86 @@
 use itertools::Itertools;
 use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
 use std::fs;
-use std::ops::{Add, Div, Mul};
+use std::ops::{Add, Mul};
 
 struct Vector {
     x: i32,
     y: i32,
 }
 
+#[derive(Debug)]
+enum ParseError {
+    MissingField {
+        line: usize,
+        field: &'static str,
+    },
+    MalformedField {
+        line: usize,
+        field: &'static str,
+        text: String,
+    },
+    InvalidNumber {
+        line: usize,
+        text: String,
+    },
+    UnexpectedToken {
+        line: usize,
+        text: String,
+    },
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseError::MissingField { line, field } => {
+                write!(f, "line {}: missing `{}=` field", line, field)
+            }
+            ParseError::MalformedField { line, field, text } => {
+                write!(f, "line {}: expected `{}=x,y`, got `{}`", line, field, text)
+            }
+            ParseError::InvalidNumber { line, text } => {
+                write!(f, "line {}: invalid number `{}`", line, text)
+            }
+            ParseError::UnexpectedToken { line, text } => {
+                write!(f, "line {}: unexpected `{}`", line, text)
+            }
+        }
+    }
+}
+
 impl Vector {
-    fn new(data: &str) -> Vector {
-        let (x, y) = data[2..]
-            .split(",")
-            .map(|x| x.parse::<i32>().unwrap())
-            .collect_tuple()
-            .unwrap();
-        Vector { x, y }
+    fn parse(token: &str, field: &'static str, line: usize) -> Result<Vector, ParseError> {
+        let malformed = || ParseError::MalformedField {
+            line,
+            field,
+            text: token.to_string(),
+        };
+        let (x, y) = token
+            .strip_prefix(field)
+            .and_then(|rest| rest.strip_prefix('='))
+            .and_then(|rest| rest.split_once(','))
+            .ok_or_else(malformed)?;
+        let number = |text: &str| {
+            text.trim()
+                .parse::<i32>()
+                .map_err(|_| ParseError::InvalidNumber {
+                    line,
+                    text: text.to_string(),
+                })
+        };
+        Ok(Vector {
+            x: number(x)?,
+            y: number(y)?,
+        })
     }
 
-    fn reduce(&self, max_x: i32, max_y: i32) -> Vector {
+    fn reduce(&self, arena: &Arena) -> Vector {
         Vector {
-            x: (self.x + max_x) % max_x,
-            y: (self.y + max_y) % max_y,
+            x: self.x.rem_euclid(arena.width),
+            y: self.y.rem_euclid(arena.height),
         }
     }
-
-    fn distance(&self, rhs: &Vector) -> i32 {
-        (self.x - rhs.x).abs() + (self.y - rhs.y).abs()
-    }
 }
 
 impl Add for &Vector {
@@ -52,14 +105,78 @@ impl Mul<i32> for &Vector {
     }
 }
 
+struct Arena {
+    width: i32,
+    height: i32,
+}
+
+impl Arena {
+    fn new(width: i32, height: i32) -> Self {
+        Self { width, height }
+    }
+
+    fn standard() -> Self {
+        Self::new(101, 103)
+    }
+
+    fn example() -> Self {
+        Self::new(11, 7)
+    }
+
+    // picks the smallest puzzle arena that holds every robot; for other inputs it falls back to
+    // the furthest robot, which is too small when no robot starts on the last row or column
+    fn infer(robots: &[Robot]) -> Self {
+        let width = robots.iter().map(|r| r.pos.x + 1).max().unwrap_or(1);
+        let height = robots.iter().map(|r| r.pos.y + 1).max().unwrap_or(1);
+        [Arena::example(), Arena::standard()]
+            .into_iter()
+            .find(|arena| width <= arena.width && height <= arena.height)
+            .unwrap_or(Arena::new(width, height))
+    }
+
+    fn quadrant(&self, pos: &Vector) -> Option<(i32, i32)> {
+        Some((half(self.width, pos.x)?, half(self.height, pos.y)?))
+    }
+}
+
+fn half(size: i32, pos: i32) -> Option<i32> {
+    if size % 2 == 1 && pos == size / 2 {
+        None
+    } else if pos < size / 2 {
+        Some(0)
+    } else {
+        Some(1)
+    }
+}
+
 struct Robot {
     pos: Vector,
     velocity: Vector,
 }
 impl Robot {
-    fn steps(&self, count: i32, max_x: i32, max_y: i32) -> Robot {
+    fn parse(text: &str, line: usize) -> Result<Robot, ParseError> {
+        let mut tokens = text.split_whitespace();
+        let pos = tokens
+            .next()
+            .ok_or(ParseError::MissingField { line, field: "p" })?;
+        let velocity = tokens
+            .next()
+            .ok_or(ParseError::MissingField { line, field: "v" })?;
+        if let Some(token) = tokens.next() {
+            return Err(ParseError::UnexpectedToken {
+                line,
+                text: token.to_string(),
+            });
+        }
+        Ok(Robot {
+            pos: Vector::parse(pos, "p", line)?,
+            velocity: Vector::parse(velocity, "v", line)?,
+        })
+    }
+
+    fn steps(&self, count: i32, arena: &Arena) -> Robot {
         Robot {
-            pos: (&self.pos + &(&self.velocity.reduce(max_x, max_y) * count)).reduce(max_x, max_y),
+            pos: (&self.pos + &(&self.velocity.reduce(arena) * count)).reduce(arena),
             velocity: Vector {
                 x: self.velocity.x,
                 y: self.velocity.y,
@@ -67,27 +184,25 @@ impl Robot {
         }
     }
 
-    fn quadrant(&self, max_x: i32, max_y: i32) -> Option<(i32, i32)> {
-        let a = self.find_split(max_x, self.pos.x);
-        let b = self.find_split(max_y, self.pos.y);
-        a.map(|x| b.map(|y| (x, y))).flatten()
+    fn quadrant(&self, arena: &Arena) -> Option<(i32, i32)> {
+        arena.quadrant(&self.pos)
     }
+}
 
-    fn find_split(&self, max: i32, pos: i32) -> Option<i32> {
-        let mid = max.div(2) + 1;
-        if (pos + 1) % mid == 0 {
-            None
-        } else {
-            Some((pos + 1) / mid)
-        }
-    }
+fn parse(content: &str) -> Result<Vec<Robot>, ParseError> {
+    content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| Robot::parse(line, i + 1))
+        .collect()
 }
 
-fn display(robots: &Vec<Robot>, max_x: i32, max_y: i32) -> String {
+fn display(robots: &Vec<Robot>, arena: &Arena) -> String {
     let taken: HashSet<(i32, i32)> = robots.iter().map(|r| (r.pos.x, r.pos.y)).collect();
     let mut result = String::new();
-    for y in 0..max_y {
-        for x in 0..max_x {
+    for y in 0..arena.height {
+        for x in 0..arena.width {
             if taken.contains(&(x, y)) {
                 result.push_str("R");
             } else {
@@ -100,57 +215,125 @@ fn display(robots: &Vec<Robot>, max_x: i32, max_y: i32) -> String {
     result
 }
 
-fn part2(robots: &Vec<Robot>) -> i32 {
-    let max_x = 101;
-    let max_y = 103;
-    let mut step = 1;
-    loop {
-        let current = robots.iter().map(|r| r.steps(step, max_x, max_y)).collect();
-        if has_tree(&current) {
-            println!("{}", display(&current, max_x, max_y));
-            return step;
-        }
-        step += 1;
+const MIN_TREE_CONFIDENCE: f64 = 0.4;
+
+struct TreeSighting {
+    step: i32,
+    confidence: f64,
+}
+
+// robots line up into the picture on both axes at once, and each axis repeats with its own period,
+// so the step with the tightest x spread and the step with the tightest y spread pin it down
+fn find_tree(robots: &[Robot], arena: &Arena, min_confidence: f64) -> Option<TreeSighting> {
+    if robots.is_empty() {
+        return None;
     }
+    let (step_x, confidence_x) = tightest_step(arena.width, |step| {
+        robots
+            .iter()
+            .map(|r| (r.pos.x + r.velocity.x * step).rem_euclid(arena.width))
+            .collect()
+    });
+    let (step_y, confidence_y) = tightest_step(arena.height, |step| {
+        robots
+            .iter()
+            .map(|r| (r.pos.y + r.velocity.y * step).rem_euclid(arena.height))
+            .collect()
+    });
+    let confidence = confidence_x.min(confidence_y);
+    if confidence < min_confidence {
+        return None;
+    }
+    let (step, _) = crt(
+        (step_x as i64, arena.width as i64),
+        (step_y as i64, arena.height as i64),
+    )?;
+    Some(TreeSighting {
+        step: step as i32,
+        confidence,
+    })
 }
 
-fn has_tree(robots: &Vec<Robot>) -> bool {
-    let mut buddies = 0;
-    for r in robots {
-        for r2 in robots {
-            if r.pos.distance(&r2.pos) == 1 {
-                buddies += 1;
-                break;
-            }
-        }
+fn tightest_step(period: i32, positions: impl Fn(i32) -> Vec<i32>) -> (i32, f64) {
+    let variances = (0..period)
+        .map(|step| variance(&positions(step)))
+        .collect_vec();
+    let mean = variances.iter().sum::<f64>() / variances.len() as f64;
+    let (step, min) = variances
+        .iter()
+        .enumerate()
+        .min_by(|a, b| a.1.total_cmp(b.1))
+        .unwrap();
+    let confidence = if mean > 0.0 { 1.0 - min / mean } else { 0.0 };
+    (step as i32, confidence)
+}
+
+fn variance(values: &[i32]) -> f64 {
+    let n = values.len() as f64;
+    let mean = values.iter().map(|&v| v as f64).sum::<f64>() / n;
+    values
+        .iter()
+        .map(|&v| (v as f64 - mean).powi(2))
+        .sum::<f64>()
+        / n
+}
+
+fn crt((a1, m1): (i64, i64), (a2, m2): (i64, i64)) -> Option<(i64, i64)> {
+    let (g, p, _) = extended_gcd(m1, m2);
+    if (a2 - a1) % g != 0 {
+        return None;
+    }
+    let lcm = m1 / g * m2;
+    let k = ((a2 - a1) / g * p).rem_euclid(m2 / g);
+    Some(((a1 + m1 * k).rem_euclid(lcm), lcm))
+}
+
+fn extended_gcd(a: i64, b: i64) -> (i64, i64, i64) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, x, y) = extended_gcd(b, a % b);
+        (g, y, x - (a / b) * y)
     }
-    buddies > robots.len() / 2
 }
 
-fn part1(robots: &Vec<Robot>) -> usize {
-    let max_x = 101;
-    let max_y = 103;
+fn part2(robots: &Vec<Robot>, arena: &Arena) -> Option<i32> {
+    let sighting = find_tree(robots, arena, MIN_TREE_CONFIDENCE)?;
+    let current = robots
+        .iter()
+        .map(|r| r.steps(sighting.step, arena))
+        .collect();
+    println!("{}", display(&current, arena));
+    println!("confidence {:.2}", sighting.confidence);
+    Some(sighting.step)
+}
+
+fn part1(robots: &Vec<Robot>, arena: &Arena) -> usize {
     let quadrant_count = robots
         .iter()
-        .map(|r| r.steps(100, max_x, max_y))
-        .map(|r| r.quadrant(max_x, max_y))
+        .map(|r| r.steps(100, arena))
+        .map(|r| r.quadrant(arena))
         .filter(|r| r.is_some())
         .map(|r| r.unwrap())
         .counts();
-    quadrant_count.values().product()
+    (0..2)
+        .cartesian_product(0..2)
+        .map(|quadrant| quadrant_count.get(&quadrant).copied().unwrap_or(0))
+        .product()
 }
 pub(crate) fn solve() {
     let content = fs::read_to_string("14.txt").unwrap();
-    let robots = content
-        .lines()
-        .map(|line| {
-            line.split(" ")
-                .map(|c| Vector::new(c))
-                .collect_tuple()
-                .unwrap()
-        })
-        .map(|(pos, velocity)| Robot { pos, velocity })
-        .collect();
-    println!("{}", part1(&robots));
-    println!("{}", part2(&robots));
+    let robots = match parse(&content) {
+        Ok(robots) => robots,
+        Err(error) => {
+            println!("{}", error);
+            return;
+        }
+    };
+    let arena = Arena::infer(&robots);
+    println!("{}", part1(&robots, &arena));
+    match part2(&robots, &arena) {
+        Some(step) => println!("{}", step),
+        None => println!("no tree found"),
+    }
 }
@@ -1,187 +1,414 @@
 use itertools::Itertools;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::{Display, Formatter};
 use std::fs;
 
-#[derive(PartialEq, Copy, Clone)]
+#[derive(PartialEq, Copy, Clone, Debug)]
 enum CellType {
     Empty,
-    Box,
-    BoxL,
-    BoxR,
+    Box(usize),
     Wall,
 }
 
-impl CellType {
-    fn new(c: &char) -> Self {
-        match c {
-            'O' => CellType::Box,
-            '[' => CellType::BoxL,
-            ']' => CellType::BoxR,
-            '#' => CellType::Wall,
-            _ => CellType::Empty,
+#[derive(Debug, PartialEq)]
+enum Problem {
+    MissingMoves,
+    UnknownMove { list: usize, index: usize, c: char },
+    UnknownCell { at: (i64, i64), c: char },
+    UnterminatedBox { at: (i64, i64) },
+    StrayBoxEdge { at: (i64, i64) },
+    OpenBoundary { at: (i64, i64) },
+    RobotCount { expected: usize, found: usize },
+    RobotBlocked { robot: usize, at: (i64, i64) },
+    UnknownRobot { robot: usize, robots: usize },
+    BoxCount { expected: usize, found: usize },
+    BoxArea { expected: i64, found: i64 },
+    BrokenBox { id: usize },
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::MissingMoves => write!(f, "no blank line between the map and the moves"),
+            Problem::UnknownMove { list, index, c } => {
+                write!(f, "unknown move `{}` at {} in move list {}", c, index, list)
+            }
+            Problem::UnknownCell { at, c } => write!(f, "unknown cell `{}` at {:?}", c, at),
+            Problem::UnterminatedBox { at } => write!(f, "box at {:?} is not closed", at),
+            Problem::StrayBoxEdge { at } => write!(f, "box edge at {:?} has no box", at),
+            Problem::OpenBoundary { at } => write!(f, "{:?} is not enclosed by walls", at),
+            Problem::RobotCount { expected, found } => {
+                write!(f, "expected {} robots, found {}", expected, found)
+            }
+            Problem::RobotBlocked { robot, at } => {
+                write!(f, "robot {} stands on a wall or box at {:?}", robot, at)
+            }
+            Problem::UnknownRobot { robot, robots } => {
+                write!(
+                    f,
+                    "move for robot {}, but there are {} robots",
+                    robot, robots
+                )
+            }
+            Problem::BoxCount { expected, found } => {
+                write!(f, "expected {} boxes, found {}", expected, found)
+            }
+            Problem::BoxArea { expected, found } => {
+                write!(f, "expected {} box cells, found {}", expected, found)
+            }
+            Problem::BrokenBox { id } => write!(f, "box {} is not intact", id),
+        }
+    }
+}
+
+struct Invariants {
+    boxes: usize,
+    area: i64,
+}
+
+struct Violation {
+    moves: usize,
+    problems: Vec<Problem>,
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "after {} moves: {}",
+            self.moves,
+            self.problems.iter().join(", ")
+        )
+    }
+}
+
+#[derive(Clone)]
+struct WarehouseBox {
+    id: usize,
+    origin: (i64, i64),
+    width: i64,
+    height: i64,
+}
+
+impl WarehouseBox {
+    fn cells(&self) -> impl Iterator<Item = (i64, i64)> + '_ {
+        (0..self.height).flat_map(move |dy| {
+            (0..self.width).map(move |dx| (self.origin.0 + dx, self.origin.1 + dy))
+        })
+    }
+
+    fn symbol(&self, (x, y): (i64, i64)) -> char {
+        let (single, open, close) = if y == self.origin.1 + self.height - 1 {
+            ('O', '[', ']')
+        } else {
+            ('o', '{', '}')
+        };
+        if self.width == 1 {
+            single
+        } else if x == self.origin.0 {
+            open
+        } else if x == self.origin.0 + self.width - 1 {
+            close
+        } else {
+            '='
         }
     }
 }
 
+#[derive(Clone)]
 struct Board {
     cells: HashMap<(i64, i64), CellType>,
-    robot: (i64, i64),
+    boxes: Vec<WarehouseBox>,
+    robots: Vec<(i64, i64)>,
 }
 
 impl Board {
-    fn new(content: &str) -> Board {
-        let mut cells = HashMap::new();
-        let mut robot = (0, 0);
-        for (y, line) in content.lines().enumerate() {
-            for (x, c) in line.chars().enumerate() {
-                if c == '@' {
-                    robot = (x as i64, y as i64);
+    /// Boxes are `O` or `[`, `=`..., `]` on the row where they end; rows of a taller box
+    /// above that are drawn as `o` or `{`, `=`..., `}` at the same columns.
+    fn new(content: &str) -> Result<Board, Vec<Problem>> {
+        let mut board = Board {
+            cells: HashMap::new(),
+            boxes: vec![],
+            robots: vec![],
+        };
+        let mut problems = vec![];
+        let mut above: HashMap<(i64, i64), ((i64, i64), i64)> = HashMap::new();
+        for (y, line) in content.lines().chain(std::iter::once("")).enumerate() {
+            let chars = line.chars().collect_vec();
+            let mut below = HashMap::new();
+            let mut x = 0;
+            while x < chars.len() {
+                let at = (x as i64, y as i64);
+                let segment = match chars[x] {
+                    '#' => {
+                        board.cells.insert(at, CellType::Wall);
+                        None
+                    }
+                    '.' => {
+                        board.cells.insert(at, CellType::Empty);
+                        None
+                    }
+                    '@' => {
+                        board.robots.push(at);
+                        board.cells.insert(at, CellType::Empty);
+                        None
+                    }
+                    'O' => Some((1, false)),
+                    'o' => Some((1, true)),
+                    open @ ('[' | '{') => {
+                        let close = if open == '[' { ']' } else { '}' };
+                        let inner = chars[x + 1..].iter().take_while(|&&c| c == '=').count();
+                        if chars.get(x + inner + 1) == Some(&close) {
+                            Some((inner as i64 + 2, open == '{'))
+                        } else {
+                            problems.push(Problem::UnterminatedBox { at });
+                            x += inner;
+                            None
+                        }
+                    }
+                    ']' | '}' | '=' => {
+                        problems.push(Problem::StrayBoxEdge { at });
+                        None
+                    }
+                    c => {
+                        problems.push(Problem::UnknownCell { at, c });
+                        None
+                    }
+                };
+                if let Some((width, continues)) = segment {
+                    let (origin, height) = above
+                        .remove(&(at.0, width))
+                        .map_or((at, 1), |(origin, height)| (origin, height + 1));
+                    if continues {
+                        below.insert((at.0, width), (origin, height));
+                    } else {
+                        board.add_box(origin, width, height);
+                    }
+                    x += width as usize - 1;
                 }
-                cells.insert((x as i64, y as i64), CellType::new(&c));
+                x += 1;
             }
+            problems.extend(
+                above
+                    .values()
+                    .map(|&(at, _)| at)
+                    .sorted_by_key(|&(x, y)| (y, x))
+                    .map(|at| Problem::UnterminatedBox { at }),
+            );
+            above = below;
+        }
+        if problems.is_empty() {
+            Ok(board)
+        } else {
+            Err(problems)
         }
-        Board { cells, robot }
     }
 
-    fn step(&mut self, direction: &Direction) {
-        match direction {
-            Direction::Left => self.horizontal_step(direction),
-            Direction::Right => self.horizontal_step(direction),
-            Direction::Up => self.vertical_step(direction),
-            Direction::Down => self.vertical_step(direction),
+    fn parse(content: &str, expected_robots: usize) -> Result<Board, Vec<Problem>> {
+        let board = Board::new(content)?;
+        let problems = board.validate(expected_robots);
+        if problems.is_empty() {
+            Ok(board)
+        } else {
+            Err(problems)
         }
     }
 
-    fn horizontal_step(&mut self, direction: &Direction) {
-        if self.horizontal_step_check(self.robot, direction) {
-            let (x, y) = self.robot;
-            let (dx, _) = direction.delta();
-            self.make_horizontal_step((x + dx, y), direction, &CellType::Empty);
-            self.robot = (self.robot.0 + dx, self.robot.1);
+    fn validate(&self, expected_robots: usize) -> Vec<Problem> {
+        let mut problems = self
+            .cells
+            .iter()
+            .filter(|&(_, cell)| cell != &CellType::Wall)
+            .filter(|&(&(x, y), _)| {
+                [(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]
+                    .iter()
+                    .any(|neighbour| !self.cells.contains_key(neighbour))
+            })
+            .map(|(&at, _)| at)
+            .sorted_by_key(|&(x, y)| (y, x))
+            .map(|at| Problem::OpenBoundary { at })
+            .collect_vec();
+        if self.robots.len() != expected_robots {
+            problems.push(Problem::RobotCount {
+                expected: expected_robots,
+                found: self.robots.len(),
+            });
         }
+        problems.extend(self.check_invariants(&self.invariants()));
+        problems
     }
 
-    fn horizontal_step_check(&self, (x, y): (i64, i64), direction: &Direction) -> bool {
-        let (dx, _) = direction.delta();
-        let next_cell = self.cells.get(&(x + dx, y)).unwrap();
-        match next_cell {
-            CellType::Wall => false,
-            CellType::Empty => true,
-            _ => self.horizontal_step_check((x + dx, y), direction),
+    fn invariants(&self) -> Invariants {
+        Invariants {
+            boxes: self.boxes.len(),
+            area: self.boxes.iter().map(|b| b.width * b.height).sum(),
         }
     }
 
-    fn make_horizontal_step(
-        &mut self,
-        (x, y): (i64, i64),
-        direction: &Direction,
-        previous: &CellType,
-    ) {
-        let (dx, _) = direction.delta();
-        let next_cell = *self.cells.get(&(x, y)).unwrap();
-        match next_cell {
-            CellType::Wall => return,
-            CellType::Empty => {
-                self.cells.insert((x, y), *previous);
+    fn check_invariants(&self, invariants: &Invariants) -> Vec<Problem> {
+        let mut problems = vec![];
+        let box_cells = self
+            .cells
+            .values()
+            .filter_map(|cell| match cell {
+                CellType::Box(id) => Some(*id),
+                _ => None,
+            })
+            .counts();
+        if box_cells.len() != invariants.boxes {
+            problems.push(Problem::BoxCount {
+                expected: invariants.boxes,
+                found: box_cells.len(),
+            });
+        }
+        let area = box_cells.values().sum::<usize>() as i64;
+        if area != invariants.area {
+            problems.push(Problem::BoxArea {
+                expected: invariants.area,
+                found: area,
+            });
+        }
+        for b in &self.boxes {
+            let intact = b
+                .cells()
+                .all(|cell| self.cells.get(&cell) == Some(&CellType::Box(b.id)))
+                && box_cells.get(&b.id).copied().unwrap_or(0) as i64 == b.width * b.height;
+            if !intact {
+                problems.push(Problem::BrokenBox { id: b.id });
             }
-            next_type => {
-                self.cells.insert((x, y), *previous);
-                self.make_horizontal_step((x + dx, y), direction, &next_type);
+        }
+        for (robot, &at) in self.robots.iter().enumerate() {
+            if self.cells.get(&at) != Some(&CellType::Empty) {
+                problems.push(Problem::RobotBlocked { robot, at });
             }
         }
+        problems
     }
 
-    fn vertical_step(&mut self, direction: &Direction) {
-        if self.vertical_step_check(self.robot, direction) {
-            let (x, y) = self.robot;
-            let (_, dy) = direction.delta();
-            self.make_vertical_step((x, y + dy), direction, &CellType::Empty);
-            self.robot = (self.robot.0, self.robot.1 + dy);
+    fn add_box(&mut self, origin: (i64, i64), width: i64, height: i64) -> usize {
+        let id = self.boxes.len();
+        let added = WarehouseBox {
+            id,
+            origin,
+            width,
+            height,
+        };
+        for cell in added.cells() {
+            self.cells.insert(cell, CellType::Box(id));
         }
+        self.boxes.push(added);
+        id
     }
 
-    fn vertical_step_check(&self, (x, y): (i64, i64), direction: &Direction) -> bool {
-        let (_, dy) = direction.delta();
-        let next_cell = self.cells.get(&(x, y + dy)).unwrap();
-        match next_cell {
-            CellType::Wall => false,
-            CellType::Empty => true,
-            CellType::Box => self.vertical_step_check((x, y + dy), direction),
-            CellType::BoxL => {
-                self.vertical_step_check((x, y + dy), direction)
-                    && return self.vertical_step_check((x + 1, y + dy), direction)
-            }
-            CellType::BoxR => {
-                self.vertical_step_check((x, y + dy), direction)
-                    && return self.vertical_step_check((x - 1, y + dy), direction)
+    fn widen(&self, factor: i64) -> Board {
+        let mut board = Board {
+            cells: HashMap::new(),
+            boxes: vec![],
+            robots: self.robots.iter().map(|&(x, y)| (x * factor, y)).collect(),
+        };
+        for (&(x, y), cell) in &self.cells {
+            let cell = match cell {
+                CellType::Wall => CellType::Wall,
+                _ => CellType::Empty,
+            };
+            for i in 0..factor {
+                board.cells.insert((x * factor + i, y), cell);
             }
         }
+        for b in &self.boxes {
+            board.add_box(
+                (b.origin.0 * factor, b.origin.1),
+                b.width * factor,
+                b.height,
+            );
+        }
+        board
     }
 
-    fn make_vertical_step(
-        &mut self,
-        (x, y): (i64, i64),
-        direction: &Direction,
-        previous: &CellType,
-    ) {
-        let (_, dy) = direction.delta();
-        let next_cell = self.cells.get(&(x, y)).unwrap();
-        match next_cell {
-            CellType::Wall => return,
-            CellType::Empty => {
-                self.cells.insert((x, y), *previous);
+    fn step(&mut self, robot: usize, direction: &Direction) -> Result<Option<Vec<usize>>, Problem> {
+        if robot >= self.robots.len() {
+            return Err(Problem::UnknownRobot {
+                robot,
+                robots: self.robots.len(),
+            });
+        }
+        let pushed = self.pushed_boxes(robot, direction);
+        if let Some(pushed) = &pushed {
+            self.shift(robot, pushed, direction.delta());
+        }
+        Ok(pushed)
+    }
+
+    fn pushed_boxes(&self, robot: usize, direction: &Direction) -> Option<Vec<usize>> {
+        let (dx, dy) = direction.delta();
+        let (x, y) = self.robots[robot];
+        let mut pushed = vec![];
+        let mut seen = HashSet::new();
+        let mut to_check = vec![(x + dx, y + dy)];
+        while let Some(pos) = to_check.pop() {
+            if self.robots.contains(&pos) {
+                return None;
             }
-            CellType::Box => {
-                self.cells.insert((x, y), *previous);
-                self.make_vertical_step((x, y + dy), direction, &CellType::Box);
+            match self.cells.get(&pos) {
+                None | Some(CellType::Wall) => return None,
+                Some(CellType::Empty) => {}
+                Some(&CellType::Box(id)) => {
+                    if seen.insert(id) {
+                        pushed.push(id);
+                        to_check.extend(self.boxes[id].cells().map(|(cx, cy)| (cx + dx, cy + dy)));
+                    }
+                }
             }
-            CellType::BoxL => {
-                self.cells.insert((x, y), *previous);
-                self.cells.insert((x + 1, y), CellType::Empty);
-                self.make_vertical_step((x, y + dy), direction, &CellType::BoxL);
-                self.make_vertical_step((x + 1, y + dy), direction, &CellType::BoxR);
+        }
+        Some(pushed)
+    }
+
+    fn shift(&mut self, robot: usize, ids: &[usize], (dx, dy): (i64, i64)) {
+        for &id in ids {
+            for cell in self.boxes[id].cells() {
+                self.cells.insert(cell, CellType::Empty);
             }
-            CellType::BoxR => {
-                self.cells.insert((x, y), *previous);
-                self.cells.insert((x - 1, y), CellType::Empty);
-                self.make_vertical_step((x, y + dy), direction, &CellType::BoxR);
-                self.make_vertical_step((x - 1, y + dy), direction, &CellType::BoxL);
+        }
+        for &id in ids {
+            let moved = &mut self.boxes[id];
+            moved.origin = (moved.origin.0 + dx, moved.origin.1 + dy);
+            for cell in moved.cells() {
+                self.cells.insert(cell, CellType::Box(id));
             }
         }
+        let (x, y) = self.robots[robot];
+        self.robots[robot] = (x + dx, y + dy);
     }
 
-    fn is_cell(&self, current: &(i64, i64), target: &CellType) -> bool {
-        self.cells.get(current).filter(|&c| c.eq(target)).is_some()
+    fn score_with(&self, scoring: impl Fn(&WarehouseBox) -> i64) -> i64 {
+        self.boxes.iter().map(scoring).sum()
     }
 
     fn score(&self) -> i64 {
-        self.cells
-            .iter()
-            .filter(|&(_, cell)| cell.eq(&CellType::Box) || cell.eq(&CellType::BoxL))
-            .map(|((x, y), _)| x + 100 * y)
-            .sum()
+        self.score_with(gps)
     }
 }
 
+fn gps(b: &WarehouseBox) -> i64 {
+    b.origin.0 + 100 * b.origin.1
+}
+
 impl Display for Board {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let mut res = String::new();
-        let dim = *self.cells.keys().map(|(_, y)| y).max().unwrap();
-        for y in 0..dim + 1 {
-            for x in 0..(dim + 1) * 2 {
-                if self.robot.eq(&(x, y)) {
+        let max_x = *self.cells.keys().map(|(x, _)| x).max().unwrap();
+        let max_y = *self.cells.keys().map(|(_, y)| y).max().unwrap();
+        for y in 0..max_y + 1 {
+            for x in 0..max_x + 1 {
+                if self.robots.contains(&(x, y)) {
                     res.push('@');
-                } else if self.is_cell(&(x, y), &CellType::Empty) {
-                    res.push('.');
-                } else if self.is_cell(&(x, y), &CellType::Wall) {
-                    res.push('#');
-                } else if self.is_cell(&(x, y), &CellType::Box) {
-                    res.push('0');
-                } else if self.is_cell(&(x, y), &CellType::BoxL) {
-                    res.push('[');
-                } else if self.is_cell(&(x, y), &CellType::BoxR) {
-                    res.push(']');
+                } else {
+                    match self.cells.get(&(x, y)) {
+                        Some(CellType::Empty) => res.push('.'),
+                        Some(CellType::Wall) => res.push('#'),
+                        Some(&CellType::Box(id)) => res.push(self.boxes[id].symbol((x, y))),
+                        None => res.push(' '),
+                    }
                 }
             }
             res.push('\n');
@@ -190,6 +417,7 @@ impl Display for Board {
     }
 }
 
+#[derive(Copy, Clone)]
 enum Direction {
     Left,
     Right,
@@ -198,13 +426,22 @@ enum Direction {
 }
 
 impl Direction {
-    fn new(c: char) -> Direction {
+    fn new(c: char) -> Option<Direction> {
         match c {
-            '^' => Direction::Up,
-            'v' => Direction::Down,
-            '>' => Direction::Right,
-            '<' => Direction::Left,
-            _ => panic!(),
+            '^' => Some(Direction::Up),
+            'v' => Some(Direction::Down),
+            '>' => Some(Direction::Right),
+            '<' => Some(Direction::Left),
+            _ => None,
+        }
+    }
+
+    fn symbol(&self) -> char {
+        match self {
+            Direction::Up => '^',
+            Direction::Down => 'v',
+            Direction::Right => '>',
+            Direction::Left => '<',
         }
     }
 
@@ -217,36 +454,253 @@ impl Direction {
         }
     }
 }
-fn solve_board(mut board: Board, directions: &Vec<Direction>) -> i64 {
-    for d in directions {
-        board.step(d);
+
+fn parse_moves(m: &str) -> Result<Vec<Vec<Direction>>, Vec<Problem>> {
+    let mut problems = vec![];
+    let lists = m
+        .split("\n\n")
+        .enumerate()
+        .map(|(list, text)| {
+            text.chars()
+                .filter(|c| !c.is_whitespace())
+                .enumerate()
+                .filter_map(|(index, c)| {
+                    let direction = Direction::new(c);
+                    if direction.is_none() {
+                        problems.push(Problem::UnknownMove { list, index, c });
+                    }
+                    direction
+                })
+                .collect_vec()
+        })
+        .collect_vec();
+    if problems.is_empty() {
+        Ok(lists)
+    } else {
+        Err(problems)
+    }
+}
+
+fn interleave(lists: &[Vec<Direction>]) -> Vec<(usize, Direction)> {
+    let longest = lists.iter().map(|list| list.len()).max().unwrap_or(0);
+    (0..longest)
+        .flat_map(|i| {
+            lists
+                .iter()
+                .enumerate()
+                .filter_map(move |(robot, list)| list.get(i).map(|&d| (robot, d)))
+        })
+        .collect()
+}
+
+type BoxMove = (usize, (i64, i64), (i64, i64));
+
+struct Move {
+    index: usize,
+    robot: usize,
+    direction: Direction,
+    robot_from: (i64, i64),
+    robot_to: (i64, i64),
+    boxes: Vec<BoxMove>,
+}
+
+impl Move {
+    fn succeeded(&self) -> bool {
+        self.robot_from != self.robot_to
+    }
+
+    fn box_ids(&self) -> Vec<usize> {
+        self.boxes.iter().map(|&(id, _, _)| id).collect()
+    }
+}
+
+impl Display for Move {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} robot {} {} {} {:?} -> {:?}",
+            self.index,
+            self.robot,
+            self.direction.symbol(),
+            if self.succeeded() { "ok" } else { "blocked" },
+            self.robot_from,
+            self.robot_to
+        )?;
+        for (id, from, to) in &self.boxes {
+            write!(f, " box {} {:?} -> {:?}", id, from, to)?;
+        }
+        Ok(())
+    }
+}
+
+struct Simulator {
+    board: Board,
+    moves: Vec<(usize, Direction)>,
+    log: Vec<Move>,
+    cursor: usize,
+    invariants: Option<Invariants>,
+    violation: Option<Violation>,
+}
+
+impl Simulator {
+    fn new(board: Board, moves: &[(usize, Direction)]) -> Self {
+        Self {
+            board,
+            moves: moves.to_vec(),
+            log: vec![],
+            cursor: 0,
+            invariants: None,
+            violation: None,
+        }
+    }
+
+    fn debug(board: Board, moves: &[(usize, Direction)]) -> Self {
+        let invariants = board.invariants();
+        Self {
+            invariants: Some(invariants),
+            ..Self::new(board, moves)
+        }
+    }
+
+    fn check(&mut self) {
+        if self.violation.is_some() {
+            return;
+        }
+        if let Some(invariants) = &self.invariants {
+            let problems = self.board.check_invariants(invariants);
+            if !problems.is_empty() {
+                self.violation = Some(Violation {
+                    moves: self.cursor,
+                    problems,
+                });
+            }
+        }
+    }
+
+    fn step(&mut self) -> Option<&Move> {
+        if self.cursor < self.log.len() {
+            return self.redo();
+        }
+        let (robot, direction) = *self.moves.get(self.cursor)?;
+        let (dx, dy) = direction.delta();
+        let pushed = match self.board.step(robot, &direction) {
+            Ok(pushed) => pushed,
+            Err(problem) => {
+                self.violation = Some(Violation {
+                    moves: self.cursor,
+                    problems: vec![problem],
+                });
+                return None;
+            }
+        };
+        let robot_to = self.board.robots[robot];
+        let robot_from = match pushed {
+            Some(_) => (robot_to.0 - dx, robot_to.1 - dy),
+            None => robot_to,
+        };
+        let boxes = pushed
+            .unwrap_or_default()
+            .into_iter()
+            .map(|id| {
+                let moved = &self.board.boxes[id];
+                let (x, y) = moved.origin;
+                (moved.id, (x - dx, y - dy), (x, y))
+            })
+            .collect();
+        self.log.push(Move {
+            index: self.cursor,
+            robot,
+            direction,
+            robot_from,
+            robot_to,
+            boxes,
+        });
+        self.cursor += 1;
+        self.check();
+        self.log.last()
+    }
+
+    fn undo(&mut self) -> Option<&Move> {
+        self.cursor = self.cursor.checked_sub(1)?;
+        let last = &self.log[self.cursor];
+        if last.succeeded() {
+            let (dx, dy) = last.direction.delta();
+            self.board.shift(last.robot, &last.box_ids(), (-dx, -dy));
+        }
+        self.check();
+        self.log.get(self.cursor)
+    }
+
+    fn redo(&mut self) -> Option<&Move> {
+        let next = self.log.get(self.cursor)?;
+        if next.succeeded() {
+            self.board
+                .shift(next.robot, &next.box_ids(), next.direction.delta());
+        }
+        self.cursor += 1;
+        self.check();
+        self.log.get(self.cursor - 1)
+    }
+
+    fn jump(&mut self, target: usize) {
+        let target = target.min(self.moves.len());
+        while self.cursor > target {
+            self.undo();
+        }
+        while self.cursor < target && self.step().is_some() {}
+    }
+
+    fn run(&mut self) {
+        self.jump(self.moves.len());
+    }
+
+    fn replay_log(&self) -> String {
+        self.log[..self.cursor].iter().join("\n")
+    }
+}
+
+fn solve_board(mut board: Board, moves: &Vec<(usize, Direction)>) -> Result<i64, Problem> {
+    for (robot, d) in moves {
+        board.step(*robot, d)?;
     }
     println!("{}", board);
-    board.score()
+    Ok(board.score())
 }
-fn part2(b: &str, directions: &Vec<Direction>) -> i64 {
-    let board_big = Board::new(
-        b.replace('#', "##")
-            .replace('O', "[]")
-            .replace('.', "..")
-            .replace('@', "@.")
-            .as_str(),
-    );
-    solve_board(board_big, directions)
+fn part2(board: &Board, moves: &Vec<(usize, Direction)>) -> Result<i64, Problem> {
+    solve_board(board.widen(2), moves)
 }
 
-fn part1(b: &str, directions: &Vec<Direction>) -> i64 {
-    let board = Board::new(b);
-    solve_board(board, directions)
+fn part1(board: &Board, moves: &Vec<(usize, Direction)>) -> Result<i64, Problem> {
+    solve_board(board.clone(), moves)
 }
 pub(crate) fn solve() {
     let content = fs::read_to_string("15.txt").unwrap();
-    let (b, m) = content.split("\n\n").collect_tuple().unwrap();
-    let directions = m
-        .chars()
-        .filter(|c| !c.eq(&'\n'))
-        .map(Direction::new)
-        .collect();
-    println!("{}", part1(b, &directions));
-    println!("{}", part2(b, &directions));
+    let (b, m) = match content.split_once("\n\n") {
+        Some(sections) => sections,
+        None => {
+            println!("{}", Problem::MissingMoves);
+            return;
+        }
+    };
+    let lists = match parse_moves(m) {
+        Ok(lists) => lists,
+        Err(problems) => {
+            println!("{}", problems.iter().join("\n"));
+            return;
+        }
+    };
+    let board = match Board::parse(b, lists.len()) {
+        Ok(board) => board,
+        Err(problems) => {
+            println!("{}", problems.iter().join("\n"));
+            return;
+        }
+    };
+    let moves = interleave(&lists);
+    for result in [part1(&board, &moves), part2(&board, &moves)] {
+        match result {
+            Ok(score) => println!("{}", score),
+            Err(problem) => println!("{}", problem),
+        }
+    }
 }
@@ -1,5 +1,6 @@
 use itertools::Itertools;
 use std::cmp::Ordering;
+use std::fmt::{Display, Formatter};
 use std::fs;
 
 struct OrderingRule {
@@ -56,6 +57,89 @@ impl OrderingRules {
         }
     }
 
+    fn violations<'a>(&'a self, update: &Update) -> Vec<Violation<'a>> {
+        update
+            .pages
+            .iter()
+            .enumerate()
+            .tuple_combinations()
+            .flat_map(|((second_index, x), (first_index, y))| {
+                self.rules
+                    .iter()
+                    .filter(move |rule| !rule.is_valid(x, y))
+                    .map(move |rule| Violation {
+                        rule,
+                        first_index,
+                        second_index,
+                    })
+            })
+            .collect()
+    }
+
+    // a page pair is inverted when the rules, followed transitively, put the later page first;
+    // the kept pages are a largest set without inverted pairs, i.e. a maximum antichain of the
+    // inversion order, read off a maximum matching (Dilworth/König) so the move count is minimal
+    fn min_fix(&self, update: &Update) -> Option<Fix> {
+        let pages = &update.pages;
+        let n = pages.len();
+        let mut before: Vec<Vec<bool>> = (0..n)
+            .map(|i| {
+                (0..n)
+                    .map(|j| i != j && !self.is_valid_pair(&pages[j], &pages[i]))
+                    .collect()
+            })
+            .collect();
+        for k in 0..n {
+            for i in 0..n {
+                for j in 0..n {
+                    if before[i][k] && before[k][j] {
+                        before[i][j] = true;
+                    }
+                }
+            }
+        }
+        if (0..n).any(|i| before[i][i]) {
+            return None;
+        }
+        let inverted = |i: usize, j: usize| i < j && before[j][i];
+        let mut partner: Vec<Option<usize>> = vec![None; n];
+        for i in 0..n {
+            augment(i, &inverted, &mut partner, &mut vec![false; n]);
+        }
+        let mut reached_left = vec![false; n];
+        let mut reached_right = vec![false; n];
+        let mut to_visit: Vec<usize> = (0..n).filter(|i| !partner.contains(&Some(*i))).collect();
+        while let Some(i) = to_visit.pop() {
+            if reached_left[i] {
+                continue;
+            }
+            reached_left[i] = true;
+            for j in 0..n {
+                if inverted(i, j) && !reached_right[j] {
+                    reached_right[j] = true;
+                    to_visit.extend(partner[j]);
+                }
+            }
+        }
+        let kept: Vec<bool> = (0..n)
+            .map(|i| reached_left[i] && !reached_right[i])
+            .collect();
+        let precedes = |i: usize, j: usize| before[i][j] || (kept[i] && kept[j] && i < j);
+        let mut order: Vec<usize> = vec![];
+        while order.len() < n {
+            let next = (0..n).find(|&i| {
+                !order.contains(&i) && (0..n).all(|j| order.contains(&j) || !precedes(j, i))
+            })?;
+            order.push(next);
+        }
+        Some(Fix {
+            moved: (0..n).filter(|&i| !kept[i]).collect(),
+            update: Update {
+                pages: order.iter().map(|&i| pages[i]).collect(),
+            },
+        })
+    }
+
     fn sort(&self, update: &Update) -> Update {
         Update {
             pages: update
@@ -68,6 +152,60 @@ impl OrderingRules {
     }
 }
 
+fn augment(
+    left: usize,
+    edge: &impl Fn(usize, usize) -> bool,
+    partner: &mut [Option<usize>],
+    seen: &mut [bool],
+) -> bool {
+    for right in 0..partner.len() {
+        if edge(left, right) && !seen[right] {
+            seen[right] = true;
+            let free = match partner[right] {
+                None => true,
+                Some(other) => augment(other, edge, partner, seen),
+            };
+            if free {
+                partner[right] = Some(left);
+                return true;
+            }
+        }
+    }
+    false
+}
+
+struct Violation<'a> {
+    rule: &'a OrderingRule,
+    first_index: usize,
+    second_index: usize,
+}
+
+impl Display for Violation<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}|{} violated: {} at index {} comes after {} at index {}",
+            self.rule.first,
+            self.rule.second,
+            self.rule.first,
+            self.first_index,
+            self.rule.second,
+            self.second_index
+        )
+    }
+}
+
+struct Fix {
+    moved: Vec<usize>,
+    update: Update,
+}
+
+impl Fix {
+    fn moves(&self) -> usize {
+        self.moved.len()
+    }
+}
+
 struct Update {
     pages: Vec<u32>,
 }
@@ -123,6 +261,28 @@ fn part1(ordering_rules: &OrderingRules, updates: &Updates) -> u32 {
         .sum()
 }
 
+fn explain(ordering_rules: &OrderingRules, updates: &Updates) {
+    for update in &updates.updates {
+        let violations = ordering_rules.violations(update);
+        if violations.is_empty() {
+            continue;
+        }
+        println!("{}", update.pages.iter().join(","));
+        for violation in violations {
+            println!("  {}", violation);
+        }
+        match ordering_rules.min_fix(update) {
+            Some(fix) => println!(
+                "  {} moves (indices {}): {}",
+                fix.moves(),
+                fix.moved.iter().join(","),
+                fix.update.pages.iter().join(",")
+            ),
+            None => println!("  no order satisfies every rule"),
+        }
+    }
+}
+
 pub(crate) fn solve() {
     let content = fs::read_to_string("5.txt").unwrap();
     let ordering_rules = OrderingRules::new(&content);
@@ -1,7 +1,30 @@
 use itertools::Itertools;
 use std::collections::{HashMap, HashSet};
+use std::fmt::{Display, Formatter};
 use std::fs;
-#[derive(Eq, Hash, PartialEq, Clone)]
+
+#[derive(Debug)]
+enum BoardError {
+    UnknownCell { line: usize, column: usize, c: char },
+    NoGuard,
+    PolicyCount { guards: usize, policies: usize },
+}
+
+impl Display for BoardError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BoardError::UnknownCell { line, column, c } => {
+                write!(f, "line {}, column {}: unknown cell {:?}", line, column, c)
+            }
+            BoardError::NoGuard => write!(f, "no guard on the board"),
+            BoardError::PolicyCount { guards, policies } => {
+                write!(f, "{} guards but {} turn policies", guards, policies)
+            }
+        }
+    }
+}
+
+#[derive(Eq, Hash, PartialEq, Clone, Copy)]
 enum Direction {
     Left,
     Right,
@@ -19,6 +42,24 @@ impl Direction {
         }
     }
 
+    fn turn_left(&self) -> Self {
+        match self {
+            Direction::Left => Direction::Down,
+            Direction::Right => Direction::Up,
+            Direction::Up => Direction::Left,
+            Direction::Down => Direction::Right,
+        }
+    }
+
+    fn marker(&self) -> char {
+        match self {
+            Direction::Left => '<',
+            Direction::Right => '>',
+            Direction::Up => '^',
+            Direction::Down => 'v',
+        }
+    }
+
     fn delta(&self) -> (i32, i32) {
         match self {
             Direction::Left => (-1, 0),
@@ -32,26 +73,66 @@ impl Direction {
         let (dx, dy) = self.delta();
         (x + dx, y + dy)
     }
+
+    fn distance_ahead(&self, (x, y): (i32, i32), (tx, ty): (i32, i32)) -> Option<i32> {
+        let (dx, dy) = self.delta();
+        let distance = match dx {
+            0 if tx == x => (ty - y) * dy,
+            0 => return None,
+            _ if ty == y => (tx - x) * dx,
+            _ => return None,
+        };
+        Some(distance).filter(|&d| d > 0)
+    }
 }
 
 #[derive(PartialEq, Copy, Clone)]
 enum CellType {
     Path,
     Obstacle,
-    Start,
+    Start(Direction),
 }
 
 impl CellType {
-    fn new(c: &char) -> Self {
+    fn new(c: &char) -> Option<Self> {
         match c {
-            '#' => CellType::Obstacle,
-            '.' => CellType::Path,
-            _ => CellType::Start,
+            '#' => Some(CellType::Obstacle),
+            '.' => Some(CellType::Path),
+            '^' => Some(CellType::Start(Direction::Up)),
+            'v' => Some(CellType::Start(Direction::Down)),
+            '<' => Some(CellType::Start(Direction::Left)),
+            '>' => Some(CellType::Start(Direction::Right)),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum TurnPolicy {
+    Right,
+    Left,
+    Alternating,
+}
+
+impl TurnPolicy {
+    fn turn(&self, direction: Direction, turns_made: usize) -> Direction {
+        match self {
+            TurnPolicy::Right => direction.turn_right(),
+            TurnPolicy::Left => direction.turn_left(),
+            TurnPolicy::Alternating if turns_made.is_multiple_of(2) => direction.turn_right(),
+            TurnPolicy::Alternating => direction.turn_left(),
+        }
+    }
+
+    fn phase(&self, turns_made: usize) -> usize {
+        match self {
+            TurnPolicy::Alternating => turns_made % 2,
+            _ => 0,
         }
     }
 }
 
-#[derive(Eq, Hash, PartialEq)]
+#[derive(Eq, Hash, PartialEq, Clone)]
 struct State {
     pos: (i32, i32),
     direction: Direction,
@@ -61,6 +142,7 @@ struct State {
 enum WalkState {
     OutOfBounds,
     Looped,
+    Collided,
 }
 
 struct WalkResult {
@@ -73,33 +155,79 @@ impl WalkResult {
         self.visited_states.iter().map(|state| state.pos).collect()
     }
 }
+
+struct CombinedWalkResult {
+    walks: Vec<WalkResult>,
+}
+
+impl CombinedWalkResult {
+    fn coverage(&self) -> Vec<usize> {
+        self.walks
+            .iter()
+            .map(|walk| walk.visited_cells().len())
+            .collect()
+    }
+
+    fn visited_cells(&self) -> HashSet<(i32, i32)> {
+        self.walks
+            .iter()
+            .flat_map(|walk| walk.visited_cells())
+            .collect()
+    }
+
+    fn looped(&self) -> usize {
+        self.walks
+            .iter()
+            .filter(|walk| walk.state == WalkState::Looped)
+            .count()
+    }
+}
+
+struct Guard {
+    state: State,
+    policy: TurnPolicy,
+    turns_made: usize,
+    walk_state: Option<WalkState>,
+    visited_states: HashSet<State>,
+}
+
 struct Board {
-    start: (i32, i32),
+    start: State,
+    guards: Vec<State>,
     cells: HashMap<(i32, i32), CellType>,
 }
 
 impl Board {
-    fn new(content: &str) -> Self {
+    fn new(content: &str) -> Result<Self, BoardError> {
         let max_y = content.lines().count() as i32;
         let cells: HashMap<(i32, i32), CellType> = content
-            .split('\n')
+            .lines()
             .enumerate()
-            .map(|(row_index, row)| {
-                row.chars()
-                    .map(|c| CellType::new(&c))
-                    .enumerate()
-                    .map(move |(col_index, cell)| {
-                        ((col_index as i32, max_y - row_index as i32), cell)
-                    })
+            .flat_map(|(row_index, row)| {
+                row.chars().enumerate().map(move |(col_index, c)| {
+                    let cell = CellType::new(&c).ok_or(BoardError::UnknownCell {
+                        line: row_index + 1,
+                        column: col_index + 1,
+                        c,
+                    })?;
+                    Ok(((col_index as i32, max_y - row_index as i32), cell))
+                })
             })
-            .flatten()
-            .collect();
-        let start = cells
+            .collect::<Result<_, _>>()?;
+        let guards: Vec<State> = cells
             .iter()
-            .find_or_first(|&(_pos, c)| c.eq(&CellType::Start))
-            .map(|(&(x, y), _c)| (x, y))
-            .unwrap();
-        Self { start, cells }
+            .filter_map(|(&pos, &c)| match c {
+                CellType::Start(direction) => Some(State { pos, direction }),
+                _ => None,
+            })
+            .sorted_by_key(|state| (-state.pos.1, state.pos.0))
+            .collect();
+        let start = guards.first().ok_or(BoardError::NoGuard)?.clone();
+        Ok(Self {
+            start,
+            guards,
+            cells,
+        })
     }
 
     fn is_legal(&self, pos: (i32, i32)) -> bool {
@@ -114,12 +242,12 @@ impl Board {
     }
     fn propagate_guard(&self) -> WalkResult {
         let mut visited_states: HashSet<State> = HashSet::new();
-        let mut current_cell = self.start;
-        let mut current_direction = Direction::Up;
+        let mut current_cell = self.start.pos;
+        let mut current_direction = self.start.direction;
         loop {
             let current_state = State {
                 pos: current_cell,
-                direction: current_direction.clone(),
+                direction: current_direction,
             };
             if visited_states.contains(&current_state) {
                 return WalkResult {
@@ -141,29 +269,302 @@ impl Board {
             }
         }
     }
+
+    fn propagate_guards(&self, policies: &[TurnPolicy]) -> Result<CombinedWalkResult, BoardError> {
+        if self.guards.len() != policies.len() {
+            return Err(BoardError::PolicyCount {
+                guards: self.guards.len(),
+                policies: policies.len(),
+            });
+        }
+        let mut guards: Vec<Guard> = self
+            .guards
+            .iter()
+            .zip(policies)
+            .map(|(state, &policy)| Guard {
+                state: state.clone(),
+                policy,
+                turns_made: 0,
+                walk_state: None,
+                visited_states: HashSet::new(),
+            })
+            .collect();
+        let mut seen_configurations: HashSet<Vec<(State, usize)>> = HashSet::new();
+        loop {
+            for guard in guards.iter_mut().filter(|guard| guard.walk_state.is_none()) {
+                guard.visited_states.insert(guard.state.clone());
+            }
+            let configuration = guards
+                .iter()
+                .filter(|guard| guard.walk_state.is_none())
+                .map(|guard| (guard.state.clone(), guard.policy.phase(guard.turns_made)))
+                .collect_vec();
+            if configuration.is_empty() {
+                break;
+            }
+            if !seen_configurations.insert(configuration) {
+                for guard in guards.iter_mut().filter(|guard| guard.walk_state.is_none()) {
+                    guard.walk_state = Some(WalkState::Looped);
+                }
+                break;
+            }
+            for index in 0..guards.len() {
+                if guards[index].walk_state.is_some() {
+                    continue;
+                }
+                let state = &guards[index].state;
+                let next_cell = state.direction.step(state.pos);
+                let blocker = guards.iter().position(|other| {
+                    other.state.pos == next_cell && other.walk_state != Some(WalkState::OutOfBounds)
+                });
+                let guard = &mut guards[index];
+                if let Some(other) = blocker {
+                    guard.walk_state = Some(WalkState::Collided);
+                    guards[other].walk_state = Some(WalkState::Collided);
+                } else if self.is_oob(next_cell) {
+                    guard.walk_state = Some(WalkState::OutOfBounds);
+                } else if self.is_legal(next_cell) {
+                    guard.state.pos = next_cell;
+                } else {
+                    guard.state.direction =
+                        guard.policy.turn(guard.state.direction, guard.turns_made);
+                    guard.turns_made += 1;
+                }
+            }
+        }
+        Ok(CombinedWalkResult {
+            walks: guards
+                .into_iter()
+                .map(|guard| WalkResult {
+                    state: guard.walk_state.unwrap(),
+                    visited_states: guard.visited_states,
+                })
+                .collect(),
+        })
+    }
+
+    fn first_approaches(&self) -> HashMap<(i32, i32), State> {
+        let mut approaches = HashMap::new();
+        let mut current_cell = self.start.pos;
+        let mut current_direction = self.start.direction;
+        let mut visited_states: HashSet<State> = HashSet::new();
+        while visited_states.insert(State {
+            pos: current_cell,
+            direction: current_direction,
+        }) {
+            let next_cell = current_direction.step(current_cell);
+            if self.is_oob(next_cell) {
+                break;
+            } else if self.is_legal(next_cell) {
+                if next_cell != self.start.pos {
+                    approaches.entry(next_cell).or_insert(State {
+                        pos: current_cell,
+                        direction: current_direction,
+                    });
+                }
+                current_cell = next_cell
+            } else {
+                current_direction = current_direction.turn_right();
+            }
+        }
+        approaches
+    }
+
+    fn next_state_with_obstacle(&self, state: &State, obstacle: (i32, i32)) -> Option<State> {
+        let next_cell = state.direction.step(state.pos);
+        if self.is_oob(next_cell) {
+            None
+        } else if next_cell != obstacle && self.is_legal(next_cell) {
+            Some(State {
+                pos: next_cell,
+                direction: state.direction,
+            })
+        } else {
+            Some(State {
+                pos: state.pos,
+                direction: state.direction.turn_right(),
+            })
+        }
+    }
+
+    fn trace_loop(&self, start: &State, obstacle: (i32, i32)) -> Option<GuardLoop> {
+        let mut path: Vec<State> = vec![];
+        let mut indices: HashMap<State, usize> = HashMap::new();
+        let mut current = Some(start.clone());
+        while let Some(state) = current {
+            if let Some(&entry_index) = indices.get(&state) {
+                return Some(GuardLoop {
+                    obstacle,
+                    cycle: path.split_off(entry_index),
+                });
+            }
+            indices.insert(state.clone(), path.len());
+            current = self.next_state_with_obstacle(&state, obstacle);
+            path.push(state);
+        }
+        None
+    }
+
+    fn render_loop(&self, guard_loop: &GuardLoop) -> String {
+        let mut marks: HashMap<(i32, i32), char> = HashMap::new();
+        for state in &guard_loop.cycle {
+            let mark = match state.direction {
+                Direction::Left | Direction::Right => '-',
+                Direction::Up | Direction::Down => '|',
+            };
+            marks
+                .entry(state.pos)
+                .and_modify(|c| {
+                    if *c != mark {
+                        *c = '+'
+                    }
+                })
+                .or_insert(mark);
+        }
+        marks.insert(guard_loop.obstacle, 'O');
+        marks.insert(self.start.pos, self.start.direction.marker());
+        let max_x = self.cells.keys().map(|&(x, _)| x).max().unwrap();
+        let min_y = self.cells.keys().map(|&(_, y)| y).min().unwrap();
+        let max_y = self.cells.keys().map(|&(_, y)| y).max().unwrap();
+        (min_y..=max_y)
+            .rev()
+            .map(|y| {
+                (0..=max_x)
+                    .filter(|&x| !self.is_oob((x, y)))
+                    .map(|x| match marks.get(&(x, y)) {
+                        Some(&mark) => mark,
+                        None if self.is_legal((x, y)) => '.',
+                        None => '#',
+                    })
+                    .collect::<String>()
+            })
+            .join("\n")
+    }
 }
 
-fn part2(board: &Board) -> usize {
-    let result = board.propagate_guard();
-    let mut looped = 0;
-    let mut base_board: HashMap<(i32, i32), CellType> =
-        board.cells.iter().map(|(&pos, &c)| (pos, c)).collect();
-    let mut cells_to_test = result.visited_cells();
-    cells_to_test.remove(&board.start);
-    for route_cell in cells_to_test {
-        base_board.insert(route_cell, CellType::Obstacle);
-        let new_board = Board {
-            start: board.start,
-            cells: base_board,
-        };
-        let result = new_board.propagate_guard();
-        if result.state.eq(&WalkState::Looped) {
-            looped += 1;
+struct GuardLoop {
+    obstacle: (i32, i32),
+    cycle: Vec<State>,
+}
+
+impl GuardLoop {
+    fn len(&self) -> usize {
+        self.cycle.len()
+    }
+
+    fn entry(&self) -> &State {
+        &self.cycle[0]
+    }
+
+    fn directions(&self) -> Vec<Direction> {
+        self.cycle
+            .iter()
+            .map(|state| state.direction)
+            .dedup()
+            .collect()
+    }
+}
+
+struct JumpTable {
+    jumps: HashMap<State, Option<State>>,
+}
+
+impl JumpTable {
+    fn new(board: &Board) -> Self {
+        let mut jumps: HashMap<State, Option<State>> = HashMap::new();
+        let free_cells: Vec<(i32, i32)> = board
+            .cells
+            .iter()
+            .filter(|(_pos, &c)| c != CellType::Obstacle)
+            .map(|(&pos, _c)| pos)
+            .collect();
+        for direction in [
+            Direction::Left,
+            Direction::Right,
+            Direction::Up,
+            Direction::Down,
+        ] {
+            let (dx, dy) = direction.delta();
+            let ordered = free_cells
+                .iter()
+                .sorted_by_key(|&&(x, y)| -(x * dx + y * dy));
+            for &pos in ordered {
+                let next_cell = direction.step(pos);
+                let jump = if board.is_oob(next_cell) {
+                    None
+                } else if board.is_legal(next_cell) {
+                    let next_state = State {
+                        pos: next_cell,
+                        direction,
+                    };
+                    jumps[&next_state].clone()
+                } else {
+                    Some(State {
+                        pos,
+                        direction: direction.turn_right(),
+                    })
+                };
+                jumps.insert(State { pos, direction }, jump);
+            }
+        }
+        Self { jumps }
+    }
+
+    fn next(&self, state: &State, obstacle: (i32, i32)) -> Option<State> {
+        let jump = &self.jumps[state];
+        let direction = state.direction;
+        let reach = jump
+            .as_ref()
+            .map(|target| direction.distance_ahead(state.pos, target.pos).unwrap_or(0));
+        let blocked = direction
+            .distance_ahead(state.pos, obstacle)
+            .filter(|&distance| reach.is_none_or(|reach| distance <= reach));
+        match blocked {
+            Some(distance) => {
+                let (dx, dy) = direction.delta();
+                Some(State {
+                    pos: (
+                        state.pos.0 + dx * (distance - 1),
+                        state.pos.1 + dy * (distance - 1),
+                    ),
+                    direction: direction.turn_right(),
+                })
+            }
+            None => jump.clone(),
+        }
+    }
+
+    fn loops_with_obstacle(&self, start: &State, obstacle: (i32, i32)) -> bool {
+        let mut visited_states: HashSet<State> = HashSet::new();
+        let mut current = Some(start.clone());
+        while let Some(state) = current {
+            if !visited_states.insert(state.clone()) {
+                return true;
+            }
+            current = self.next(&state, obstacle);
         }
-        base_board = new_board.cells;
-        base_board.insert(route_cell, CellType::Path);
+        false
     }
-    looped
+}
+
+fn part2(board: &Board) -> usize {
+    let jump_table = JumpTable::new(board);
+    board
+        .first_approaches()
+        .iter()
+        .filter(|(&obstacle, start)| jump_table.loops_with_obstacle(start, obstacle))
+        .count()
+}
+
+fn find_loops(board: &Board) -> Vec<GuardLoop> {
+    let jump_table = JumpTable::new(board);
+    board
+        .first_approaches()
+        .iter()
+        .filter(|(&obstacle, start)| jump_table.loops_with_obstacle(start, obstacle))
+        .filter_map(|(&obstacle, start)| board.trace_loop(start, obstacle))
+        .sorted_by_key(|guard_loop| guard_loop.obstacle)
+        .collect()
 }
 
 fn part1(board: &Board) -> usize {
@@ -173,7 +574,13 @@ fn part1(board: &Board) -> usize {
 
 pub(crate) fn solve() {
     let content = fs::read_to_string("6.txt").unwrap();
-    let board = Board::new(&content);
+    let board = match Board::new(&content) {
+        Ok(board) => board,
+        Err(error) => {
+            println!("{}", error);
+            return;
+        }
+    };
     println!("{}", part1(&board));
     println!("{}", part2(&board));
 }
@@ -1,74 +1,578 @@
+use ibig::UBig;
 use itertools::Itertools;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
 use std::fs;
-use std::ops::{Add, Mul};
+use std::hash::Hash;
+use std::str::FromStr;
 
-struct Equation {
-    result: u64,
-    numbers: Vec<u64>,
+trait Number: Clone + Eq + Hash + Display + FromStr + 'static {
+    fn from_u64(value: u64) -> Self;
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    fn checked_sub(&self, other: &Self) -> Option<Self>;
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+    fn div_rem(&self, other: &Self) -> (Self, Self);
+    fn xor(&self, other: &Self) -> Self;
+
+    fn is_zero(&self) -> bool {
+        self == &Self::from_u64(0)
+    }
+}
+
+impl Number for u64 {
+    fn from_u64(value: u64) -> Self {
+        value
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        u64::checked_add(*self, *other)
+    }
+
+    fn checked_sub(&self, other: &Self) -> Option<Self> {
+        u64::checked_sub(*self, *other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        u64::checked_mul(*self, *other)
+    }
+
+    fn div_rem(&self, other: &Self) -> (Self, Self) {
+        (self / other, self % other)
+    }
+
+    fn xor(&self, other: &Self) -> Self {
+        self ^ other
+    }
+}
+
+impl Number for UBig {
+    fn from_u64(value: u64) -> Self {
+        UBig::from(value)
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn checked_sub(&self, other: &Self) -> Option<Self> {
+        match self >= other {
+            true => Some(self - other),
+            false => None,
+        }
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        Some(self * other)
+    }
+
+    fn div_rem(&self, other: &Self) -> (Self, Self) {
+        (self / other, self % other)
+    }
+
+    fn xor(&self, other: &Self) -> Self {
+        self ^ other
+    }
+}
+
+struct Equation<N: Number> {
+    result: N,
+    numbers: Vec<N>,
+}
+
+#[derive(PartialEq, Clone, Copy)]
+enum Outcome {
+    Satisfied,
+    Unsatisfied,
+    Overflow,
+}
+
+impl Outcome {
+    fn combine(self, other: impl FnOnce() -> Outcome) -> Outcome {
+        match self {
+            Outcome::Satisfied => Outcome::Satisfied,
+            Outcome::Unsatisfied => other(),
+            Outcome::Overflow => match other() {
+                Outcome::Satisfied => Outcome::Satisfied,
+                _ => Outcome::Overflow,
+            },
+        }
+    }
+}
+
+enum Application<N> {
+    Value(N),
+    Undefined,
+    Overflow,
+}
+
+struct Sequences<N> {
+    found: Vec<Vec<usize>>,
+    limit: usize,
+    sequence: Vec<usize>,
+    dead: HashSet<(usize, N)>,
+}
+
+enum Preimage<N> {
+    Values(Vec<N>),
+    Anything,
+    Overflow,
+}
+
+type Apply<N> = Box<dyn Fn(&N, &N) -> Application<N>>;
+type Invert<N> = Box<dyn Fn(&N, &N) -> Preimage<N>>;
+
+struct Operator<N: Number> {
+    symbol: String,
+    apply: Apply<N>,
+    preimage: Invert<N>,
+}
+
+impl<N: Number> Operator<N> {
+    fn add() -> Self {
+        Self {
+            symbol: "+".to_string(),
+            apply: Box::new(|x, y| match x.checked_add(y) {
+                Some(value) => Application::Value(value),
+                None => Application::Overflow,
+            }),
+            preimage: Box::new(|target, y| {
+                Preimage::Values(target.checked_sub(y).into_iter().collect())
+            }),
+        }
+    }
+
+    fn mul() -> Self {
+        Self {
+            symbol: "*".to_string(),
+            apply: Box::new(|x, y| match x.checked_mul(y) {
+                Some(value) => Application::Value(value),
+                None => Application::Overflow,
+            }),
+            preimage: Box::new(|target, y| match (target.is_zero(), y.is_zero()) {
+                (true, true) => Preimage::Anything,
+                (false, true) => Preimage::Values(vec![]),
+                _ => match target.div_rem(y) {
+                    (x, remainder) if remainder.is_zero() => Preimage::Values(vec![x]),
+                    _ => Preimage::Values(vec![]),
+                },
+            }),
+        }
+    }
+
+    fn sub() -> Self {
+        Self {
+            symbol: "-".to_string(),
+            apply: Box::new(|x, y| match x.checked_sub(y) {
+                Some(value) => Application::Value(value),
+                None => Application::Undefined,
+            }),
+            preimage: Box::new(|target, y| match target.checked_add(y) {
+                Some(x) => Preimage::Values(vec![x]),
+                None => Preimage::Overflow,
+            }),
+        }
+    }
+
+    fn div() -> Self {
+        Self {
+            symbol: "/".to_string(),
+            apply: Box::new(|x, y| match y.is_zero() {
+                true => Application::Undefined,
+                false => match x.div_rem(y) {
+                    (value, remainder) if remainder.is_zero() => Application::Value(value),
+                    _ => Application::Undefined,
+                },
+            }),
+            preimage: Box::new(|target, y| match y.is_zero() {
+                true => Preimage::Values(vec![]),
+                false => match target.checked_mul(y) {
+                    Some(x) => Preimage::Values(vec![x]),
+                    None => Preimage::Overflow,
+                },
+            }),
+        }
+    }
+
+    fn xor() -> Self {
+        Self {
+            symbol: "^".to_string(),
+            apply: Box::new(|x, y| Application::Value(x.xor(y))),
+            preimage: Box::new(|target, y| Preimage::Values(vec![target.xor(y)])),
+        }
+    }
+
+    fn concat(base: u64) -> Self {
+        Self {
+            symbol: match base {
+                10 => "||".to_string(),
+                _ => format!("||{}", base),
+            },
+            apply: Box::new(move |x, y| {
+                let value = Operator::concat_shift(y, base)
+                    .and_then(|shift| x.checked_mul(&shift))
+                    .and_then(|shifted| shifted.checked_add(y));
+                match value {
+                    Some(value) => Application::Value(value),
+                    None => Application::Overflow,
+                }
+            }),
+            preimage: Box::new(move |target, y| match Operator::concat_shift(y, base) {
+                Some(shift) => match target.div_rem(&shift) {
+                    (x, remainder) if &remainder == y => Preimage::Values(vec![x]),
+                    _ => Preimage::Values(vec![]),
+                },
+                None if target == y => Preimage::Values(vec![N::from_u64(0)]),
+                None => Preimage::Values(vec![]),
+            }),
+        }
+    }
+
+    fn concat_shift(y: &N, base: u64) -> Option<N> {
+        let base = N::from_u64(base);
+        let mut shift = base.clone();
+        let mut rest = y.div_rem(&base).0;
+        while !rest.is_zero() {
+            shift = shift.checked_mul(&base)?;
+            rest = rest.div_rem(&base).0;
+        }
+        Some(shift)
+    }
+}
+
+struct Operators<N: Number> {
+    operators: Vec<Operator<N>>,
 }
 
-impl Equation {
+impl<N: Number> Operators<N> {
+    fn new() -> Self {
+        Self { operators: vec![] }
+    }
+
+    fn add_mul() -> Self {
+        let mut operators = Operators::new();
+        operators.register(Operator::add());
+        operators.register(Operator::mul());
+        operators
+    }
+
+    fn add_mul_concat() -> Self {
+        let mut operators = Operators::add_mul();
+        operators.register(Operator::concat(10));
+        operators
+    }
+
+    fn register(&mut self, operator: Operator<N>) {
+        self.operators.push(operator);
+    }
+
+    fn apply_all(&self, x: &N, y: &N) -> Vec<(usize, Application<N>)> {
+        self.operators
+            .iter()
+            .enumerate()
+            .map(|(index, operator)| (index, (operator.apply)(x, y)))
+            .collect()
+    }
+}
+
+impl<N: Number> Equation<N> {
+    fn new(line: &str) -> Option<Self> {
+        let (result, numbers) = line.split(": ").collect_tuple()?;
+        Some(Equation {
+            result: result.parse().ok()?,
+            numbers: numbers
+                .split(" ")
+                .map(|x| x.parse().ok())
+                .collect::<Option<Vec<N>>>()?,
+        })
+    }
+
     fn is_sat_add_mul(&self) -> bool {
-        let potential_operators = [u64::add, u64::mul].iter().collect();
-        self.is_sat(&potential_operators)
+        self.is_sat(&Operators::add_mul())
     }
 
     fn is_sat_add_mul_concat(&self) -> bool {
-        let potential_operators = [u64::add, u64::mul, Equation::concat].iter().collect();
-        self.is_sat(&potential_operators)
-    }
-
-    fn concat(x: u64, y: u64) -> u64 {
-        let shift = y.to_string().len() as u32;
-        x * 10_u64.pow(shift) + y
-    }
-    fn is_sat(&self, potential_operators: &Vec<&fn(u64, u64) -> u64>) -> bool {
-        let sequence_length = self.numbers.len();
-        let operators_variations = (0..sequence_length)
-            .map(|_| potential_operators)
-            .multi_cartesian_product();
-        for operator_sequence in operators_variations {
-            let mut res = self.numbers[0];
-            for (number, op) in self.numbers[1..].iter().zip(operator_sequence) {
-                res = op(res, *number);
-                if res > self.result {
+        self.is_sat(&Operators::add_mul_concat())
+    }
+
+    fn is_sat(&self, operators: &Operators<N>) -> bool {
+        self.check(operators) == Outcome::Satisfied
+    }
+
+    fn check(&self, operators: &Operators<N>) -> Outcome {
+        self.check_prefix(&self.result, self.numbers.len(), operators)
+    }
+
+    fn check_prefix(&self, target: &N, length: usize, operators: &Operators<N>) -> Outcome {
+        if length == 1 {
+            return match &self.numbers[0] == target {
+                true => Outcome::Satisfied,
+                false => Outcome::Unsatisfied,
+            };
+        }
+        let last = &self.numbers[length - 1];
+        operators
+            .operators
+            .iter()
+            .fold(Outcome::Unsatisfied, |outcome, operator| {
+                outcome.combine(|| match (operator.preimage)(target, last) {
+                    Preimage::Values(values) => {
+                        values.iter().fold(Outcome::Unsatisfied, |outcome, x| {
+                            outcome.combine(|| self.check_prefix(x, length - 1, operators))
+                        })
+                    }
+                    Preimage::Anything => self.check_anything(length - 1, operators),
+                    Preimage::Overflow => Outcome::Overflow,
+                })
+            })
+    }
+
+    fn check_anything(&self, length: usize, operators: &Operators<N>) -> Outcome {
+        let mut known = HashMap::new();
+        self.defined_from(&self.numbers[0], 1, length, operators, &mut known)
+    }
+
+    // whether any operator assignment keeps numbers[..length] defined, memoised on (index, value)
+    fn defined_from(
+        &self,
+        value: &N,
+        index: usize,
+        length: usize,
+        operators: &Operators<N>,
+        known: &mut HashMap<(usize, N), Outcome>,
+    ) -> Outcome {
+        if index == length {
+            return Outcome::Satisfied;
+        }
+        if let Some(&outcome) = known.get(&(index, value.clone())) {
+            return outcome;
+        }
+        let outcome = operators
+            .apply_all(value, &self.numbers[index])
+            .into_iter()
+            .fold(Outcome::Unsatisfied, |outcome, (_, application)| {
+                outcome.combine(|| match application {
+                    Application::Value(next) => {
+                        self.defined_from(&next, index + 1, length, operators, known)
+                    }
+                    Application::Undefined => Outcome::Unsatisfied,
+                    Application::Overflow => Outcome::Overflow,
+                })
+            });
+        known.insert((index, value.clone()), outcome);
+        outcome
+    }
+
+    fn count_sat(&self, operators: &Operators<N>) -> usize {
+        self.count_prefix(&self.result, self.numbers.len(), operators)
+    }
+
+    fn count_prefix(&self, target: &N, length: usize, operators: &Operators<N>) -> usize {
+        if length == 1 {
+            return (&self.numbers[0] == target) as usize;
+        }
+        let last = &self.numbers[length - 1];
+        operators
+            .operators
+            .iter()
+            .map(|operator| match (operator.preimage)(target, last) {
+                Preimage::Values(values) => values
+                    .iter()
+                    .map(|x| self.count_prefix(x, length - 1, operators))
+                    .fold(0, usize::saturating_add),
+                Preimage::Anything => self.count_defined(length - 1, operators),
+                Preimage::Overflow => 0,
+            })
+            .fold(0, usize::saturating_add)
+    }
+
+    fn count_defined(&self, length: usize, operators: &Operators<N>) -> usize {
+        let mut counts = HashMap::from([(self.numbers[0].clone(), 1usize)]);
+        for number in &self.numbers[1..length] {
+            let mut next_counts: HashMap<N, usize> = HashMap::new();
+            for (value, count) in &counts {
+                for (_, application) in operators.apply_all(value, number) {
+                    if let Application::Value(next) = application {
+                        let entry = next_counts.entry(next).or_insert(0);
+                        *entry = entry.saturating_add(*count);
+                    }
+                }
+            }
+            counts = next_counts;
+        }
+        counts
+            .values()
+            .fold(0, |total, &count| total.saturating_add(count))
+    }
+
+    fn witnesses(&self, operators: &Operators<N>, limit: usize) -> Vec<Vec<usize>> {
+        self.witnesses_prefix(&self.result, self.numbers.len(), operators, limit)
+    }
+
+    fn witnesses_prefix(
+        &self,
+        target: &N,
+        length: usize,
+        operators: &Operators<N>,
+        limit: usize,
+    ) -> Vec<Vec<usize>> {
+        if length == 1 {
+            return match &self.numbers[0] == target && limit > 0 {
+                true => vec![vec![]],
+                false => vec![],
+            };
+        }
+        let last = &self.numbers[length - 1];
+        let mut witnesses = vec![];
+        for (index, operator) in operators.operators.iter().enumerate() {
+            if witnesses.len() >= limit {
+                break;
+            }
+            let remaining = limit - witnesses.len();
+            let prefixes = match (operator.preimage)(target, last) {
+                Preimage::Values(values) => {
+                    let mut prefixes = vec![];
+                    for x in values {
+                        if prefixes.len() >= remaining {
+                            break;
+                        }
+                        prefixes.extend(self.witnesses_prefix(
+                            &x,
+                            length - 1,
+                            operators,
+                            remaining - prefixes.len(),
+                        ));
+                    }
+                    prefixes
+                }
+                Preimage::Anything => self.defined_sequences(length - 1, operators, remaining),
+                Preimage::Overflow => vec![],
+            };
+            witnesses.extend(prefixes.into_iter().map(|mut sequence| {
+                sequence.push(index);
+                sequence
+            }));
+        }
+        witnesses
+    }
+
+    fn defined_sequences(
+        &self,
+        length: usize,
+        operators: &Operators<N>,
+        limit: usize,
+    ) -> Vec<Vec<usize>> {
+        let mut search = Sequences {
+            found: vec![],
+            limit,
+            sequence: vec![],
+            dead: HashSet::new(),
+        };
+        self.collect_defined(&self.numbers[0], 1, length, operators, &mut search);
+        search.found
+    }
+
+    fn collect_defined(
+        &self,
+        value: &N,
+        index: usize,
+        length: usize,
+        operators: &Operators<N>,
+        search: &mut Sequences<N>,
+    ) -> bool {
+        if search.found.len() >= search.limit {
+            return true;
+        }
+        if index == length {
+            search.found.push(search.sequence.clone());
+            return true;
+        }
+        if search.dead.contains(&(index, value.clone())) {
+            return false;
+        }
+        let mut any = false;
+        for (operator, application) in operators.apply_all(value, &self.numbers[index]) {
+            if let Application::Value(next) = application {
+                search.sequence.push(operator);
+                any |= self.collect_defined(&next, index + 1, length, operators, search);
+                search.sequence.pop();
+                if search.found.len() >= search.limit {
                     break;
                 }
             }
-            if res == self.result {
-                return true;
+        }
+        if !any {
+            search.dead.insert((index, value.clone()));
+        }
+        any
+    }
+
+    fn evaluate(&self, sequence: &[usize], operators: &Operators<N>) -> Application<N> {
+        let mut value = self.numbers[0].clone();
+        for (number, &index) in self.numbers[1..].iter().zip(sequence) {
+            match (operators.operators[index].apply)(&value, number) {
+                Application::Value(next) => value = next,
+                other => return other,
             }
         }
-        false
+        Application::Value(value)
     }
-}
 
-fn parse_line(line: &str) -> Equation {
-    let (result, numbers) = line.split(": ").collect_tuple().unwrap();
-    Equation {
-        result: result.parse().unwrap(),
-        numbers: numbers.split(" ").map(|x| x.parse().unwrap()).collect(),
+    fn expression(&self, sequence: &[usize], operators: &Operators<N>) -> String {
+        let mut expression = self.numbers[0].to_string();
+        for (number, &index) in self.numbers[1..].iter().zip(sequence) {
+            expression.push_str(&format!(
+                " {} {}",
+                operators.operators[index].symbol, number
+            ));
+        }
+        expression
     }
+
+    fn expressions(&self, operators: &Operators<N>, limit: usize) -> Vec<String> {
+        self.witnesses(operators, limit)
+            .iter()
+            .map(|sequence| self.expression(sequence, operators))
+            .collect()
+    }
+}
+
+fn parse<N: Number>(content: &str) -> Option<Vec<Equation<N>>> {
+    content.lines().map(Equation::new).collect()
 }
 
-fn part2(equations: &Vec<Equation>) -> u64 {
+fn overflows(equations: &[Equation<u64>]) -> bool {
+    let add_mul = Operators::add_mul();
+    let add_mul_concat = Operators::add_mul_concat();
+    equations.iter().any(|eq| {
+        eq.check(&add_mul) == Outcome::Overflow || eq.check(&add_mul_concat) == Outcome::Overflow
+    })
+}
+
+fn part2<N: Number>(equations: &Vec<Equation<N>>) -> Option<N> {
     equations
         .iter()
         .filter(|&eq| eq.is_sat_add_mul_concat())
-        .map(|eq| eq.result)
-        .sum()
+        .try_fold(N::from_u64(0), |total, eq| total.checked_add(&eq.result))
 }
-fn part1(equations: &Vec<Equation>) -> u64 {
+fn part1<N: Number>(equations: &Vec<Equation<N>>) -> Option<N> {
     equations
         .iter()
         .filter(|&eq| eq.is_sat_add_mul())
-        .map(|eq| eq.result)
-        .sum()
+        .try_fold(N::from_u64(0), |total, eq| total.checked_add(&eq.result))
+}
+
+fn solve_with<N: Number>(equations: &Vec<Equation<N>>) -> Option<(N, N)> {
+    Some((part1(equations)?, part2(equations)?))
 }
 
 pub(crate) fn solve() {
     let content = fs::read_to_string("7.txt").unwrap();
-    let equations = content.lines().map(parse_line).collect();
-    println!("{}", part1(&equations));
-    println!("{}", part2(&equations));
+    let (part1, part2) = parse::<u64>(&content)
+        .filter(|equations| !overflows(equations))
+        .and_then(|equations| solve_with(&equations))
+        .map(|(part1, part2)| (UBig::from(part1), UBig::from(part2)))
+        .or_else(|| solve_with(&parse::<UBig>(&content)?))
+        .unwrap();
+    println!("{}", part1);
+    println!("{}", part2);
 }
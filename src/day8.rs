@@ -1,16 +1,28 @@
 use itertools::Itertools;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 
+type Pair = ((i32, i32), (i32, i32));
+
+struct FrequencyReport {
+    antinodes: HashMap<(i32, i32), Vec<Pair>>,
+}
+
 struct Board {
     cells: HashMap<(i32, i32), char>,
     antennas: HashMap<char, Vec<(i32, i32)>>,
-    size: i32,
+    width: i32,
+    height: i32,
 }
 
 impl Board {
     fn new(content: &str) -> Self {
-        let size = content.lines().count() as i32 - 1;
+        let height = content.lines().count() as i32;
+        let width = content
+            .lines()
+            .map(|line| line.chars().count())
+            .max()
+            .unwrap_or(0) as i32;
         let cells: HashMap<(i32, i32), char> = content
             .split('\n')
             .enumerate()
@@ -19,7 +31,7 @@ impl Board {
                     .map(|c| c)
                     .enumerate()
                     .map(move |(col_index, cell)| {
-                        ((col_index as i32, size - row_index as i32), cell)
+                        ((col_index as i32, height - 1 - row_index as i32), cell)
                     })
             })
             .flatten()
@@ -37,25 +49,31 @@ impl Board {
         Self {
             cells,
             antennas,
-            size,
+            width,
+            height,
         }
     }
 
     fn count_antinodes(&self, any_position: bool) -> usize {
+        self.count_antinodes_with_sight(any_position, false)
+    }
+
+    fn count_antinodes_with_sight(&self, any_position: bool, line_of_sight: bool) -> usize {
         self.antennas
             .keys()
-            .map(|c| self.get_antinodes(c, any_position))
+            .map(|c| self.get_antinodes(c, any_position, line_of_sight))
             .flatten()
             .filter(|pos| self.cells.contains_key(pos))
             .unique()
             .count()
     }
 
-    fn get_antinodes(&self, c: &char, any_position: bool) -> Vec<(i32, i32)> {
+    fn get_antinodes(&self, c: &char, any_position: bool, line_of_sight: bool) -> Vec<(i32, i32)> {
         let nodes = self.antennas.get(c).unwrap();
         nodes
             .iter()
             .combinations(2)
+            .filter(|x| !line_of_sight || self.in_line_of_sight(*x[0], *x[1]))
             .map(|x| self.calculate_antinodes(*x[0], *x[1], any_position))
             .flatten()
             .unique()
@@ -70,12 +88,101 @@ impl Board {
     ) -> Vec<(i32, i32)> {
         let dx = x2 - x1;
         let dy = y2 - y1;
-        let multipliers = if !any_position { 1..=1 } else { 0..=self.size };
-        multipliers
-            .map(|m| [(x1 - dx * m, y1 - dy * m), (x2 + dx * m, y2 + dy * m)])
-            .flatten()
+        if !any_position {
+            return vec![(x1 - dx, y1 - dy), (x2 + dx, y2 + dy)];
+        }
+        let divisor = gcd(dx.abs(), dy.abs());
+        let (step_x, step_y) = (dx / divisor, dy / divisor);
+        let forward = (0..)
+            .map(|m| (x1 + step_x * m, y1 + step_y * m))
+            .take_while(|&pos| self.is_inside(pos));
+        let backward = (1..)
+            .map(|m| (x1 - step_x * m, y1 - step_y * m))
+            .take_while(|&pos| self.is_inside(pos));
+        forward.chain(backward).collect()
+    }
+
+    fn antinode_report(&self, any_position: bool) -> HashMap<char, FrequencyReport> {
+        self.antennas
+            .iter()
+            .map(|(&c, nodes)| {
+                let mut antinodes: HashMap<(i32, i32), Vec<Pair>> = HashMap::new();
+                for (&first, &second) in nodes.iter().tuple_combinations() {
+                    for pos in self.calculate_antinodes(first, second, any_position) {
+                        if self.cells.contains_key(&pos) {
+                            antinodes.entry(pos).or_default().push((first, second));
+                        }
+                    }
+                }
+                (c, FrequencyReport { antinodes })
+            })
+            .collect()
+    }
+
+    fn render(&self, any_position: bool) -> Vec<String> {
+        let antinodes: HashSet<(i32, i32)> = self
+            .antinode_report(any_position)
+            .values()
+            .flat_map(|report| report.antinodes.keys().copied())
+            .collect();
+        (0..self.height)
+            .rev()
+            .map(|y| {
+                (0..self.width)
+                    .map(|x| match self.cells.get(&(x, y)) {
+                        Some('.') if antinodes.contains(&(x, y)) => '#',
+                        Some(&c) => c,
+                        None => ' ',
+                    })
+                    .collect()
+            })
             .collect()
     }
+
+    fn render_side_by_side(&self) -> String {
+        self.render(false)
+            .iter()
+            .zip(self.render(true))
+            .map(|(single, resonant)| format!("{}   {}", single, resonant))
+            .join("\n")
+    }
+
+    fn is_inside(&self, (x, y): (i32, i32)) -> bool {
+        (0..self.width).contains(&x) && (0..self.height).contains(&y)
+    }
+
+    fn in_line_of_sight(&self, (x1, y1): (i32, i32), (x2, y2): (i32, i32)) -> bool {
+        let dx = (x2 - x1).abs();
+        let dy = -(y2 - y1).abs();
+        let sx = (x2 - x1).signum();
+        let sy = (y2 - y1).signum();
+        let mut error = dx + dy;
+        let (mut x, mut y) = (x1, y1);
+        loop {
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                x += sx;
+            }
+            if doubled <= dx {
+                error += dx;
+                y += sy;
+            }
+            if (x, y) == (x2, y2) {
+                return true;
+            }
+            if self.cells.get(&(x, y)).is_some_and(|c| c != &'.') {
+                return false;
+            }
+        }
+    }
+}
+
+fn gcd(a: i32, b: i32) -> i32 {
+    match b {
+        0 => a,
+        _ => gcd(b, a % b),
+    }
 }
 fn part2(board: &Board) -> usize {
     board.count_antinodes(true)
@@ -1,4 +1,6 @@
-use std::collections::{HashMap, VecDeque};
+use itertools::Itertools;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashSet, VecDeque};
 use std::fmt::Display;
 use std::fs;
 
@@ -27,129 +29,372 @@ impl Display for Block {
     }
 }
 
+#[derive(Clone)]
+struct Extent {
+    id: u64,
+    start: usize,
+    len: usize,
+}
+
+impl Extent {
+    fn end(&self) -> usize {
+        self.start + self.len
+    }
+
+    fn checksum(&self) -> u64 {
+        let len = self.len as u64;
+        self.id * (len * self.start as u64 + len * (len.saturating_sub(1)) / 2)
+    }
+}
+
+enum Fit {
+    First,
+    Best,
+    Worst,
+    Next,
+}
+
+impl Fit {
+    fn choose(
+        &self,
+        gaps: &BTreeMap<usize, usize>,
+        file: &Extent,
+        cursor: usize,
+    ) -> Option<(usize, usize)> {
+        let mut candidates = gaps
+            .range(..file.start)
+            .map(|(&start, &size)| (start, size))
+            .filter(|&(_, size)| size >= file.len);
+        match self {
+            Fit::First => candidates.next(),
+            Fit::Best => candidates.min_by_key(|&(start, size)| (size, start)),
+            Fit::Worst => candidates.min_by_key(|&(start, size)| (Reverse(size), start)),
+            Fit::Next => {
+                let (before, after): (Vec<_>, Vec<_>) =
+                    candidates.partition(|&(start, _)| start < cursor);
+                after.into_iter().chain(before).next()
+            }
+        }
+    }
+}
+
+struct DefragReport {
+    checksum: u64,
+    gaps: usize,
+    largest_free_run: usize,
+    files_moved: usize,
+}
+
+#[derive(Debug)]
+enum LayoutError {
+    InvalidBlock { position: usize, text: String },
+    OutOfOrder { id: u64, expected: u64 },
+    FileTooLong { id: u64, len: usize },
+    GapTooLong { before: u64, len: usize },
+}
+
+impl Display for LayoutError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LayoutError::InvalidBlock { position, text } => {
+                write!(f, "invalid block `{}` at {}", text, position)
+            }
+            LayoutError::OutOfOrder { id, expected } => {
+                write!(
+                    f,
+                    "file {} appears where file {} was expected",
+                    id, expected
+                )
+            }
+            LayoutError::FileTooLong { id, len } => {
+                write!(f, "file {} spans {} blocks, at most 9 fit", id, len)
+            }
+            LayoutError::GapTooLong { before, len } => {
+                write!(
+                    f,
+                    "gap of {} blocks before file {} does not fit",
+                    len, before
+                )
+            }
+        }
+    }
+}
+
 #[derive(Clone)]
 struct Disk {
-    blocks: Vec<Block>,
-    file_meta: HashMap<u64, (usize, usize)>,
+    files: Vec<Extent>,
 }
 
 impl Disk {
     fn new(content: &str) -> Self {
-        let mut blocks = Vec::new();
-        let mut file_meta: HashMap<u64, (usize, usize)> = HashMap::new();
+        let mut files = Vec::new();
         let mut current_is_empty = false;
         let mut block_id = 0;
         let mut current_index = 0;
-        for c in content.chars() {
-            let number_of_blocks = c.to_string().as_str().parse().unwrap();
-            for _ in 0..number_of_blocks {
-                if current_is_empty {
-                    blocks.push(Block::Empty);
-                } else {
-                    blocks.push(Block::ID(block_id));
-                }
-                current_index += 1;
-            }
+        for c in content.trim().chars() {
+            let number_of_blocks = c.to_digit(10).unwrap() as usize;
             if !current_is_empty {
-                let file_start = current_index - number_of_blocks;
-                file_meta.insert(block_id, (file_start, number_of_blocks));
+                if number_of_blocks > 0 {
+                    files.push(Extent {
+                        id: block_id,
+                        start: current_index,
+                        len: number_of_blocks,
+                    });
+                }
                 block_id += 1;
             }
+            current_index += number_of_blocks;
             current_is_empty = !current_is_empty;
         }
-        Self { blocks, file_meta }
+        Self { files }
     }
 
     fn defrag_blocks(&mut self) {
-        let mut empty_indices = VecDeque::new();
-        let mut taken_indices = VecDeque::new();
-        for (index, block) in self.blocks.iter().enumerate() {
-            match block {
-                Block::Empty => empty_indices.push_back(index),
-                Block::ID(_) => taken_indices.push_back(index),
+        let gaps = self.find_empty_segments();
+        let mut remaining: VecDeque<Extent> = self.files.drain(..).collect();
+        let mut moved = vec![];
+        'gaps: for (gap_start, gap_size) in gaps {
+            let mut position = gap_start;
+            while position < gap_start + gap_size {
+                let last = match remaining.back_mut() {
+                    Some(last) if last.start > position => last,
+                    _ => break 'gaps,
+                };
+                let taken = last.len.min(gap_start + gap_size - position);
+                moved.push(Extent {
+                    id: last.id,
+                    start: position,
+                    len: taken,
+                });
+                last.len -= taken;
+                if last.len == 0 {
+                    remaining.pop_back();
+                }
+                position += taken;
             }
         }
-        loop {
-            let first_empty = empty_indices.pop_front().unwrap();
-            let last_taken = taken_indices.pop_back().unwrap();
-            if first_empty < last_taken {
-                self.blocks[first_empty] = self.blocks[last_taken].clone();
-                self.blocks[last_taken] = Block::Empty;
-            } else {
-                break;
+        self.files = remaining.into_iter().chain(moved).collect();
+        self.files.sort_by_key(|extent| extent.start);
+    }
+
+    fn defrag_files(&mut self) {
+        let gaps = self.find_empty_segments();
+        let largest_gap = gaps.iter().map(|&(_, size)| size).max().unwrap_or(0);
+        let mut gaps_by_size: Vec<BinaryHeap<Reverse<usize>>> =
+            (0..=largest_gap).map(|_| BinaryHeap::new()).collect();
+        for (start, size) in gaps {
+            gaps_by_size[size].push(Reverse(start));
+        }
+        let mut order: Vec<usize> = (0..self.files.len()).collect();
+        order.sort_by_key(|&index| Reverse(self.files[index].id));
+        for index in order {
+            self.defrag_file(index, &mut gaps_by_size);
+        }
+        self.files.sort_by_key(|extent| extent.start);
+    }
+
+    fn defrag_file(&mut self, index: usize, gaps_by_size: &mut [BinaryHeap<Reverse<usize>>]) {
+        let file = &mut self.files[index];
+        let best = (file.len..gaps_by_size.len())
+            .filter_map(|size| {
+                gaps_by_size[size]
+                    .peek()
+                    .map(|&Reverse(start)| (start, size))
+            })
+            .filter(|&(start, _)| start < file.start)
+            .min();
+        if let Some((gap_start, gap_size)) = best {
+            gaps_by_size[gap_size].pop();
+            file.start = gap_start;
+            let remaining = gap_size - file.len;
+            if remaining > 0 {
+                gaps_by_size[remaining].push(Reverse(gap_start + file.len));
             }
         }
     }
 
-    fn defrag_files(&mut self) {
-        let mut empty_segments = self.find_empty_segments();
-        let max_file_id = *self.file_meta.keys().max().unwrap();
-        for current_file_id in (0_u64..=max_file_id).rev() {
-            self.defrag_file(current_file_id, &mut empty_segments);
-        }
-    }
-
-    fn defrag_file(&mut self, current_file_id: u64, empty_segments: &mut Vec<(usize, usize)>) {
-        let (file_start, file_size) = *self.file_meta.get(&current_file_id).unwrap();
-        for (empty_index, (empty_start, empty_size)) in empty_segments.iter().enumerate() {
-            if file_size <= *empty_size && file_start > *empty_start {
-                self.delete_file(current_file_id);
-                self.insert_file(current_file_id, *empty_start);
-                let remaining = empty_size - file_size;
-                if remaining > 0 {
-                    let new_empty_block_start = *empty_start + file_size;
-                    empty_segments[empty_index] = (new_empty_block_start, remaining);
-                } else {
-                    empty_segments.remove(empty_index);
+    fn defrag_with(&mut self, fit: &Fit, multi_pass: bool) -> DefragReport {
+        let mut gaps: BTreeMap<usize, usize> = self.find_empty_segments().into_iter().collect();
+        let mut order: Vec<usize> = (0..self.files.len()).collect();
+        order.sort_by_key(|&index| Reverse(self.files[index].id));
+        let mut moved_files = HashSet::new();
+        let mut cursor = 0;
+        loop {
+            let mut moved_in_pass = false;
+            for &index in &order {
+                let file = &mut self.files[index];
+                if let Some((gap_start, gap_size)) = fit.choose(&gaps, file, cursor) {
+                    gaps.remove(&gap_start);
+                    if gap_size > file.len {
+                        gaps.insert(gap_start + file.len, gap_size - file.len);
+                    }
+                    Disk::release(&mut gaps, file.start, file.len);
+                    file.start = gap_start;
+                    cursor = file.end();
+                    moved_files.insert(file.id);
+                    moved_in_pass = true;
                 }
+            }
+            if !multi_pass || !moved_in_pass {
                 break;
             }
         }
+        self.files.sort_by_key(|extent| extent.start);
+        self.report(moved_files.len())
     }
 
-    fn find_empty_segments(&self) -> Vec<(usize, usize)> {
-        let mut empty_segments = vec![];
-        let mut segment_size = 0usize;
-        let mut segment_start = 0usize;
-        for (index, block) in self.blocks.iter().enumerate() {
-            match block {
-                Block::Empty => {
-                    if segment_size == 0 {
-                        segment_start = index;
-                    }
-                    segment_size += 1
-                }
-                Block::ID(_) => {
-                    if segment_size > 0 {
-                        empty_segments.push((segment_start, segment_size));
-                        segment_size = 0;
-                    }
-                }
+    fn release(gaps: &mut BTreeMap<usize, usize>, start: usize, len: usize) {
+        let mut start = start;
+        let mut len = len;
+        if let Some((&previous_start, &previous_size)) = gaps.range(..start).next_back() {
+            if previous_start + previous_size == start {
+                gaps.remove(&previous_start);
+                start = previous_start;
+                len += previous_size;
             }
         }
-        empty_segments
+        if let Some(next_size) = gaps.remove(&(start + len)) {
+            len += next_size;
+        }
+        gaps.insert(start, len);
     }
 
-    fn delete_file(&mut self, current_file_id: u64) {
-        let (start, size) = self.file_meta.get(&current_file_id).unwrap();
-        for i in 0..*size {
-            self.blocks[start + i] = Block::Empty;
+    fn report(&self, files_moved: usize) -> DefragReport {
+        let gaps = self.find_empty_segments();
+        DefragReport {
+            checksum: self.checksum(),
+            gaps: gaps.len(),
+            largest_free_run: gaps.iter().map(|&(_, size)| size).max().unwrap_or(0),
+            files_moved,
         }
     }
 
-    fn insert_file(&mut self, current_file_id: u64, new_start_index: usize) {
-        let (_, current_file_size) = self.file_meta.get(&current_file_id).unwrap();
-        for part in 0..*current_file_size {
-            self.blocks[new_start_index + part] = Block::ID(current_file_id);
+    fn find_empty_segments(&self) -> Vec<(usize, usize)> {
+        let mut end = 0;
+        self.files
+            .iter()
+            .filter_map(|file| {
+                let gap = (end, file.start - end);
+                end = file.end();
+                Some(gap).filter(|&(_, size)| size > 0)
+            })
+            .collect()
+    }
+
+    fn blocks(&self) -> Vec<Block> {
+        let mut blocks = vec![];
+        for file in &self.files {
+            blocks.resize(file.start, Block::Empty);
+            blocks.extend((0..file.len).map(|_| Block::ID(file.id)));
         }
+        blocks
     }
 
-    fn checksum(&self) -> u64 {
-        self.blocks
+    fn to_blocks(&self) -> String {
+        self.blocks().iter().join(" ")
+    }
+
+    // accepts `to_blocks` output, or the unseparated `Display` form while every id is one digit
+    fn from_blocks(view: &str) -> Result<Self, LayoutError> {
+        let view = view.trim();
+        let tokens = if view.contains(char::is_whitespace) {
+            view.split_whitespace().collect_vec()
+        } else {
+            view.char_indices()
+                .map(|(i, c)| &view[i..i + c.len_utf8()])
+                .collect_vec()
+        };
+        let mut files: Vec<Extent> = Vec::new();
+        for (index, token) in tokens.into_iter().enumerate() {
+            if token == "." {
+                continue;
+            }
+            let id = token
+                .parse::<u64>()
+                .map_err(|_| LayoutError::InvalidBlock {
+                    position: index,
+                    text: token.to_string(),
+                })?;
+            match files.last_mut() {
+                Some(last) if last.id == id && last.end() == index => last.len += 1,
+                _ => files.push(Extent {
+                    id,
+                    start: index,
+                    len: 1,
+                }),
+            }
+        }
+        Ok(Self { files })
+    }
+
+    // ids are implicit in the dense map, so a missing id becomes an empty file that can carry
+    // part of the following gap
+    fn to_dense(&self) -> Result<String, LayoutError> {
+        let mut dense = String::new();
+        let mut position = 0;
+        let mut next_id = 0;
+        for file in &self.files {
+            if file.id < next_id {
+                return Err(LayoutError::OutOfOrder {
+                    id: file.id,
+                    expected: next_id,
+                });
+            }
+            let missing = (file.id - next_id) as usize;
+            let slots = missing + usize::from(!dense.is_empty());
+            let mut gap = file.start - position;
+            if gap > 9 * slots {
+                return Err(LayoutError::GapTooLong {
+                    before: file.id,
+                    len: gap,
+                });
+            }
+            for slot in 0..slots {
+                if slot > 0 || dense.is_empty() {
+                    dense.push('0');
+                }
+                let taken = gap.min(9);
+                dense.push_str(&taken.to_string());
+                gap -= taken;
+            }
+            if file.len > 9 {
+                return Err(LayoutError::FileTooLong {
+                    id: file.id,
+                    len: file.len,
+                });
+            }
+            dense.push_str(&file.len.to_string());
+            position = file.end();
+            next_id = file.id + 1;
+        }
+        Ok(dense)
+    }
+
+    fn to_json(&self) -> String {
+        let extents = self
+            .files
             .iter()
-            .enumerate()
-            .map(|(idx, block)| idx as u64 * block.value())
-            .sum()
+            .map(|file| {
+                format!(
+                    "{{\"id\":{},\"start\":{},\"len\":{}}}",
+                    file.id, file.start, file.len
+                )
+            })
+            .join(",");
+        format!("[{}]", extents)
+    }
+
+    fn checksum(&self) -> u64 {
+        self.files.iter().map(|extent| extent.checksum()).sum()
+    }
+}
+
+impl Display for Disk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for block in self.blocks() {
+            write!(f, "{}", block)?;
+        }
+        Ok(())
     }
 }
 